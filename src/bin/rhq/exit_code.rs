@@ -0,0 +1,67 @@
+//! Exit codes of the process.
+//!
+//! These values are stable and documented, so that scripts can decide
+//! how to handle the failure (e.g. whether to retry).
+
+use std::{error, fmt};
+
+/// The command completed successfully.
+pub const SUCCESS: i32 = 0;
+/// An error which does not fall into other categories.
+pub const FAILURE: i32 = 1;
+/// The command line arguments are invalid.
+pub const USAGE: i32 = 2;
+/// The configuration file is missing or malformed.
+pub const CONFIG: i32 = 3;
+/// The cache file is missing or malformed.
+pub const CACHE: i32 = 4;
+/// The VCS command is missing or failed (e.g. network failure).
+pub const VCS: i32 = 5;
+/// The remote or local repository does not exist, or no repository matches the keywords.
+pub const NOT_FOUND: i32 = 6;
+/// The destination of repository has already existed, or the repository has local changes.
+pub const CONFLICT: i32 = 7;
+/// Some of operations in bulk operation failed.
+pub const PARTIAL: i32 = 8;
+
+/// The error which indicates some of operations in bulk failed.
+#[derive(Debug)]
+pub struct PartialFailure {
+    pub operation: &'static str,
+    pub failed: usize,
+}
+
+impl fmt::Display for PartialFailure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "failed to {} {} repositories",
+            self.operation, self.failed
+        )
+    }
+}
+
+impl error::Error for PartialFailure {}
+
+/// Determine the exit code of process from the kind of error.
+pub fn from_error(err: &anyhow::Error) -> i32 {
+    use rhq::Error::*;
+    if err.is::<PartialFailure>() {
+        return PARTIAL;
+    }
+    match err.downcast_ref::<rhq::Error>() {
+        Some(ConfigNotFound { .. }) | Some(InvalidConfig { .. }) => CONFIG,
+        Some(CacheCorrupt { .. }) | Some(CacheNotInitialized) => CACHE,
+        #[cfg(feature = "git2")]
+        Some(Git { .. }) => VCS,
+        Some(VcsNotFound { .. })
+        | Some(VcsTooOld { .. })
+        | Some(VcsFailed { .. })
+        | Some(VcsNotRegistered { .. })
+        | Some(Unsupported { .. }) => VCS,
+        Some(RemoteNotFound { .. }) | Some(NotManaged { .. }) | Some(NoMatch { .. }) => NOT_FOUND,
+        Some(DestinationExists { .. }) | Some(LocalChanges { .. }) => CONFLICT,
+        Some(InvalidQuery { .. }) | Some(VcsUndetermined { .. }) => USAGE,
+        _ => FAILURE,
+    }
+}
@@ -1,16 +1,165 @@
+use crate::exit_code;
 use anyhow::Result;
 use clap::{
     app_from_crate, crate_authors, crate_description, crate_name, crate_version, App, AppSettings,
-    SubCommand,
+    Arg, ArgMatches, ErrorKind, SubCommand,
 };
+use rhq::{
+    printer::{
+        ColorChoice, Event, Level, MessageFormat, Printer, POSSIBLE_COLOR_CHOICES,
+        POSSIBLE_MESSAGE_FORMATS,
+    },
+    query::Query,
+    Workspace,
+};
+use std::io::{self, BufRead, Write};
 
 mod add;
 mod clone;
 mod completion;
+mod doctor;
+mod gc;
 mod import;
 mod list;
 mod new;
+mod path;
 mod refresh;
+mod set_url;
+mod tag;
+mod tidy;
+mod touch;
+mod watch;
+
+/// Options available in all subcommands.
+#[derive(Debug)]
+pub struct GlobalOptions {
+    level: Level,
+    message_format: MessageFormat,
+    color: ColorChoice,
+}
+
+impl GlobalOptions {
+    fn args<'a, 'b: 'a>() -> Vec<Arg<'a, 'b>> {
+        vec![
+            Arg::from_usage("-v, --verbose 'Use verbose output (-vv for debug output)'")
+                .multiple(true)
+                .global(true),
+            Arg::from_usage("-q, --quiet 'Suppress all messages except errors'")
+                .conflicts_with("verbose")
+                .global(true),
+            Arg::from_usage("--color=[when] 'Coloring of messages'")
+                .possible_values(POSSIBLE_COLOR_CHOICES)
+                .default_value("auto")
+                .global(true),
+            Arg::from_usage("--message-format=[format] 'Output format of messages'")
+                .possible_values(POSSIBLE_MESSAGE_FORMATS)
+                .default_value("human")
+                .global(true),
+        ]
+    }
+
+    fn from_matches(m: &ArgMatches) -> GlobalOptions {
+        let level = match (m.is_present("quiet"), m.occurrences_of("verbose")) {
+            (true, _) => Level::Error,
+            (false, 0) => Level::Warn,
+            (false, 1) => Level::Info,
+            (false, _) => Level::Debug,
+        };
+        GlobalOptions {
+            level,
+            message_format: m
+                .value_of("message-format")
+                .and_then(|s| s.parse().ok())
+                .unwrap_or_default(),
+            color: m
+                .value_of("color")
+                .and_then(|s| s.parse().ok())
+                .unwrap_or_default(),
+        }
+    }
+
+    pub fn message_format(&self) -> MessageFormat {
+        self.message_format
+    }
+
+    pub fn printer(&self) -> Printer {
+        Printer::new(self.level, self.message_format, self.color)
+    }
+
+    /// Open the workspace with global options applied.
+    pub fn workspace(&self) -> Result<Workspace> {
+        Ok(Workspace::new()?.with_printer(self.printer()))
+    }
+
+    fn report_error(&self, err: &anyhow::Error) {
+        let kind = match err.downcast_ref::<rhq::Error>() {
+            Some(err) => err.kind(),
+            None => "other",
+        };
+        self.printer().emit(Event::Error {
+            kind,
+            message: format!("{:#}", err),
+        });
+    }
+}
+
+/// Ask the user for confirmation, and returns whether it is accepted.
+///
+/// The prompt is written to the standard error, so that it is not mixed into the output.
+fn confirm(message: &str) -> Result<bool> {
+    eprint!("{} [y/N] ", message);
+    io::stderr().flush()?;
+    let mut answer = String::new();
+    io::stdin().lock().read_line(&mut answer)?;
+    Ok(answer.trim().eq_ignore_ascii_case("y") || answer.trim().eq_ignore_ascii_case("yes"))
+}
+
+/// The option to select repositories by tags.
+fn tag_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::from_usage(
+        "-t, --tag=[tag]... 'Select only repositories with the tag, or in the group defined in the configuration'",
+    )
+    .number_of_values(1)
+}
+
+/// Returns the tags given by `--tag`.
+fn tags_of(m: &ArgMatches) -> Vec<String> {
+    m.values_of("tag")
+        .map(|s| s.map(ToOwned::to_owned).collect())
+        .unwrap_or_default()
+}
+
+/// Validate the query given from command line.
+fn validate_query(query: String) -> std::result::Result<(), String> {
+    query.parse::<Query>().map(drop).map_err(|e| e.to_string())
+}
+
+/// Validate the name of VCS given from command line, against the registered backends.
+fn validate_vcs(name: String) -> std::result::Result<(), String> {
+    let names: Vec<_> = rhq::vcs::backends()
+        .iter()
+        .map(|backend| backend.vcs().name())
+        .collect();
+    if names.contains(&name.as_str()) {
+        Ok(())
+    } else {
+        Err(format!("'{}' is not one of [{}]", name, names.join(", ")))
+    }
+}
+
+/// Print the error of command line parsing, and returns the exit code.
+fn usage_error(err: clap::Error) -> i32 {
+    match err.kind {
+        ErrorKind::HelpDisplayed | ErrorKind::VersionDisplayed => {
+            println!("{}", err.message);
+            exit_code::SUCCESS
+        }
+        _ => {
+            eprintln!("{}", err.message);
+            exit_code::USAGE
+        }
+    }
+}
 
 macro_rules! def_app {
     ($( $name:expr => [$t:ty: $aliases:expr], )*) => {
@@ -18,14 +167,30 @@ macro_rules! def_app {
             app_from_crate!()
                 .setting(AppSettings::VersionlessSubcommands)
                 .setting(AppSettings::SubcommandRequiredElseHelp)
+                .args(&GlobalOptions::args())
                 $( .subcommand(<$t>::app(SubCommand::with_name($name)).aliases($aliases)) )*
         }
 
-        pub fn run() -> Result<()> {
-            let matches = app().get_matches();
-            match matches.subcommand() {
-                $( ($name, Some(m)) => <$t>::from_matches(m).run(), )*
+        /// Run the application and returns the exit code.
+        pub fn run() -> i32 {
+            let matches = match app().get_matches_safe() {
+                Ok(matches) => matches,
+                Err(err) => return usage_error(err),
+            };
+            let (globals, result) = match matches.subcommand() {
+                $( ($name, Some(m)) => {
+                    let globals = GlobalOptions::from_matches(m);
+                    let result = <$t>::from_matches(m).run(&globals);
+                    (globals, result)
+                } )*
                 _ => unreachable!(),
+            };
+            match result {
+                Ok(()) => exit_code::SUCCESS,
+                Err(err) => {
+                    globals.report_error(&err);
+                    exit_code::from_error(&err)
+                }
             }
         }
     }
@@ -35,8 +200,16 @@ def_app! {
     "add"        => [self::add::AddCommand: &[]],
     "clone"      => [self::clone::CloneCommand: &["cl"]],
     "completion" => [self::completion::CompletionCommand: &["cmpl"]],
+    "doctor"     => [self::doctor::DoctorCommand: &[]],
+    "gc"         => [self::gc::GcCommand: &[]],
     "import"     => [self::import::ImportCommand: &["imp"]],
     "list"       => [self::list::ListCommand: &["ls"]],
     "new"        => [self::new::NewCommand: &[]],
+    "path"       => [self::path::PathCommand: &[]],
     "refresh"    => [self::refresh::RefreshCommand: &[]],
+    "set-url"    => [self::set_url::SetUrlCommand: &[]],
+    "tag"        => [self::tag::TagCommand: &[]],
+    "tidy"       => [self::tidy::TidyCommand: &[]],
+    "touch"      => [self::touch::TouchCommand: &[]],
+    "watch"      => [self::watch::WatchCommand: &[]],
 }
@@ -1,6 +1,11 @@
+use super::GlobalOptions;
 use anyhow::Result;
 use clap::{App, Arg, ArgMatches};
-use rhq::{query::Query, vcs::Vcs, vcs::POSSIBLE_VCS, Remote, Workspace};
+use rhq::{
+    query::Query,
+    vcs::{CloneOptions, Vcs},
+    Remote,
+};
 use std::path::{Path, PathBuf};
 
 #[derive(Debug)]
@@ -9,20 +14,31 @@ pub struct CloneCommand<'a> {
     dest: Option<PathBuf>,
     root: Option<&'a Path>,
     ssh: bool,
-    vcs: Vcs,
+    vcs: Option<Vcs>,
+    options: CloneOptions,
 }
 
 impl<'a> CloneCommand<'a> {
     pub fn app<'b: 'a>(app: App<'a, 'b>) -> App<'a, 'b> {
         app.about("Clone remote repositories, and then add it under management")
-            .arg_from_usage("<query>          'an URL or a string to determine the URL of remote repository'")
+            .arg(Arg::from_usage("<query>          'an URL or a string to determine the URL of remote repository'").validator(super::validate_query))
             .arg_from_usage("[dest]           'Destination directory of cloned repository'")
             .arg_from_usage("--root=[root]    'Path to determine the destination directory of cloned repository'")
             .arg_from_usage("-s, --ssh        'Use SSH protocol instead of HTTP(s)'")
             .arg(
-                Arg::from_usage("--vcs=[vcs] 'Used Version Control System'")
-                    .possible_values(POSSIBLE_VCS)
-                    .default_value("git"),
+                Arg::from_usage("--vcs=[vcs] 'Used Version Control System (detected from the URL if omitted)'")
+                    .validator(super::validate_vcs),
+            )
+            .arg(
+                Arg::from_usage("--depth=[depth]  'Create a shallow clone with the given number of commits'")
+                    .validator(|s| s.parse::<u32>().map(drop).map_err(|e| e.to_string())),
+            )
+            .arg_from_usage("-b, --branch=[branch] 'Check out the given branch (or channel) instead of the default'")
+            .arg_from_usage("--recursive      'Clone submodules recursively'")
+            .arg_from_usage("--bare           'Clone without working directory'")
+            .arg(
+                Arg::from_usage("[args]...        'Extra arguments passed to the clone command (after `--`)'")
+                    .last(true),
             )
     }
 
@@ -32,12 +48,22 @@ impl<'a> CloneCommand<'a> {
             dest: m.value_of("dest").map(PathBuf::from),
             root: m.value_of("root").map(Path::new),
             ssh: m.is_present("ssh"),
-            vcs: m.value_of("vcs").and_then(|s| s.parse().ok()).unwrap(),
+            vcs: m.value_of("vcs").and_then(|s| s.parse().ok()),
+            options: CloneOptions {
+                depth: m.value_of("depth").and_then(|s| s.parse().ok()),
+                branch: m.value_of("branch").map(ToOwned::to_owned),
+                recursive: m.is_present("recursive"),
+                bare: m.is_present("bare"),
+                extra_args: m
+                    .values_of("args")
+                    .map(|args| args.map(ToOwned::to_owned).collect())
+                    .unwrap_or_default(),
+            },
         }
     }
 
-    pub fn run(self) -> Result<()> {
-        let mut workspace = Workspace::new()?;
+    pub fn run(self, globals: &GlobalOptions) -> Result<()> {
+        let mut workspace = globals.workspace()?;
         if let Some(root) = self.root {
             workspace.set_root_dir(root);
         }
@@ -47,7 +73,11 @@ impl<'a> CloneCommand<'a> {
             Some(dest) => dest,
             None => workspace.resolve_query(&self.query)?,
         };
-        workspace.clone_repository(remote, &dest, self.vcs)?;
+        let vcs = match self.vcs {
+            Some(vcs) => vcs,
+            None => workspace.detect_vcs(&remote)?,
+        };
+        workspace.clone_repository(remote, &dest, vcs, &self.options)?;
 
         workspace.save_cache()?;
         Ok(())
@@ -1,3 +1,4 @@
+use super::GlobalOptions;
 use anyhow::Result;
 use clap::{App, AppSettings, Arg, ArgMatches};
 use std::path::Path;
@@ -28,12 +29,12 @@ impl<'a> CompletionCommand<'a> {
         }
     }
 
-    pub fn run(self) -> Result<()> {
+    pub fn run(self, _: &GlobalOptions) -> Result<()> {
         if let Some(path) = self.out_file {
             let mut file = ::std::fs::OpenOptions::new()
                 .write(true)
                 .create(true)
-                .append(false)
+                .truncate(true)
                 .open(path)
                 .unwrap();
             super::app().gen_completions_to(env!("CARGO_PKG_NAME"), self.shell, &mut file);
@@ -0,0 +1,137 @@
+use super::GlobalOptions;
+use anyhow::{anyhow, Result};
+use clap::{App, ArgMatches};
+use rhq::{
+    printer::{CheckStatus, Event, Printer},
+    vcs::{self, Vcs, VcsConfig},
+    Workspace,
+};
+
+#[derive(Debug)]
+pub struct DoctorCommand;
+
+impl DoctorCommand {
+    pub fn app<'a, 'b: 'a>(app: App<'a, 'b>) -> App<'a, 'b> {
+        app.about("Diagnose the configuration, cache and VCS executables")
+    }
+
+    pub fn from_matches(_m: &ArgMatches) -> DoctorCommand {
+        DoctorCommand
+    }
+
+    pub fn run(self, globals: &GlobalOptions) -> Result<()> {
+        let mut doctor = Doctor {
+            printer: globals.printer(),
+            problems: 0,
+        };
+
+        let workspace = match globals.workspace() {
+            Ok(workspace) => {
+                doctor.check_workspace(&workspace);
+                Some(workspace)
+            }
+            Err(err) => {
+                doctor.report("workspace", CheckStatus::Error, format!("{:#}", err));
+                None
+            }
+        };
+
+        for vcs in vcs::backends().iter().map(|backend| backend.vcs()) {
+            let used = workspace
+                .as_ref()
+                .and_then(|workspace| workspace.repositories())
+                .map_or(0, |repos| {
+                    repos.iter().filter(|repo| repo.vcs() == vcs).count()
+                });
+            let config = match workspace {
+                Some(ref workspace) => workspace.config().vcs(vcs).into_owned(),
+                None => VcsConfig::new(vcs),
+            };
+            doctor.check_vcs(vcs, &config, used);
+        }
+
+        match doctor.problems {
+            0 => Ok(()),
+            n => Err(anyhow!("found {} problem(s)", n)),
+        }
+    }
+}
+
+struct Doctor {
+    printer: Printer,
+    problems: usize,
+}
+
+impl Doctor {
+    fn report(&mut self, subject: &str, status: CheckStatus, message: String) {
+        if status == CheckStatus::Error {
+            self.problems += 1;
+        }
+        self.printer.emit(Event::Check {
+            subject,
+            status,
+            message,
+        });
+    }
+
+    fn check_workspace(&mut self, workspace: &Workspace) {
+        let config = workspace.config();
+        self.report(
+            "config",
+            CheckStatus::Ok,
+            config.path().display().to_string(),
+        );
+
+        if config.root_dir.is_dir() {
+            self.report(
+                "root",
+                CheckStatus::Ok,
+                config.root_dir.display().to_string(),
+            );
+        } else {
+            self.report(
+                "root",
+                CheckStatus::Warning,
+                format!("{} does not exist yet", config.root_dir.display()),
+            );
+        }
+
+        match workspace.repositories() {
+            Some(repos) => self.report(
+                "cache",
+                CheckStatus::Ok,
+                format!("{} repositories", repos.len()),
+            ),
+            None => self.report(
+                "cache",
+                CheckStatus::Warning,
+                "not initialized yet; run `rhq import` to create it".to_owned(),
+            ),
+        }
+    }
+
+    fn check_vcs(&mut self, vcs: Vcs, config: &VcsConfig, used: usize) {
+        match vcs.probe(config) {
+            Ok(capability) => {
+                let version = capability.version.map_or_else(
+                    || "unknown version".to_owned(),
+                    |v| format!("version {}", v),
+                );
+                let path = capability
+                    .path
+                    .map_or(capability.command, |path| path.display().to_string());
+                let message = format!("{} ({})", version, path);
+                self.report(vcs.name(), CheckStatus::Ok, message);
+            }
+            Err(err @ rhq::Error::VcsNotFound { .. }) if used == 0 => {
+                self.report(vcs.name(), CheckStatus::Warning, err.to_string());
+            }
+            Err(err) if used > 0 => self.report(
+                vcs.name(),
+                CheckStatus::Error,
+                format!("{} ({} managed repositories use it)", err, used),
+            ),
+            Err(err) => self.report(vcs.name(), CheckStatus::Error, err.to_string()),
+        }
+    }
+}
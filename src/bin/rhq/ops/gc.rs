@@ -0,0 +1,107 @@
+use super::{confirm, tag_arg, tags_of, GlobalOptions};
+use crate::exit_code::PartialFailure;
+use anyhow::Result;
+use clap::{App, Arg, ArgMatches};
+use rhq::printer::Event;
+use std::path::PathBuf;
+
+/// What to do with stale repositories.
+#[derive(Debug)]
+enum Action {
+    Report,
+    Archive(PathBuf),
+    Delete,
+}
+
+#[derive(Debug)]
+pub struct GcCommand {
+    days: u32,
+    action: Action,
+    yes: bool,
+    tags: Vec<String>,
+}
+
+impl GcCommand {
+    pub fn app<'a, 'b: 'a>(app: App<'a, 'b>) -> App<'a, 'b> {
+        app.about("Find repositories not used for a long time, and archive or delete them")
+            .arg(
+                Arg::from_usage(
+                    "--days=[days] 'Number of days without commits or accesses to regard repositories as stale'",
+                )
+                .default_value("365")
+                .validator(|s| s.parse::<u32>().map(drop).map_err(|e| e.to_string())),
+            )
+            .arg(
+                Arg::from_usage("--archive=[dir] 'Archive stale repositories into the directory as tarballs, mirroring the layout under the root, and then delete them'")
+                    .conflicts_with("delete"),
+            )
+            .arg_from_usage("--delete        'Delete stale repositories'")
+            .arg_from_usage("-y, --yes       'Archive or delete repositories without confirmation'")
+            .arg(tag_arg())
+    }
+
+    pub fn from_matches(m: &ArgMatches) -> GcCommand {
+        let action = match m.value_of("archive") {
+            Some(dir) => Action::Archive(PathBuf::from(dir)),
+            None if m.is_present("delete") => Action::Delete,
+            None => Action::Report,
+        };
+        GcCommand {
+            days: m.value_of("days").unwrap().parse().unwrap(),
+            action,
+            yes: m.is_present("yes"),
+            tags: tags_of(m),
+        }
+    }
+
+    pub fn run(self, globals: &GlobalOptions) -> Result<()> {
+        let mut workspace = globals.workspace()?;
+
+        let stale: Vec<PathBuf> = workspace
+            .stale_repositories(self.days)?
+            .into_iter()
+            .filter(|&(repository, ..)| workspace.has_tags(repository, &self.tags))
+            .map(|(repository, size, last_active)| {
+                workspace.emit(Event::Stale {
+                    repository,
+                    size,
+                    last_active,
+                });
+                repository.path().to_owned()
+            })
+            .collect();
+
+        let archive_dir = match self.action {
+            Action::Report => return Ok(()),
+            Action::Archive(ref dir) => Some(dir.as_path()),
+            Action::Delete => None,
+        };
+        let message = match archive_dir {
+            Some(..) => "Archive and delete",
+            None => "Delete",
+        };
+
+        let mut num_failed = 0;
+        for path in stale {
+            if !self.yes && !confirm(&format!("{} {}?", message, path.display()))? {
+                continue;
+            }
+            if let Err(e) = workspace.remove_repository(&path, archive_dir) {
+                workspace.emit(Event::Warning {
+                    message: format!("Failed: {} ({})", path.display(), e),
+                });
+                num_failed += 1;
+            }
+        }
+        workspace.save_cache()?;
+
+        if num_failed > 0 {
+            return Err(PartialFailure {
+                operation: "remove",
+                failed: num_failed,
+            }
+            .into());
+        }
+        Ok(())
+    }
+}
@@ -1,13 +1,14 @@
+use super::GlobalOptions;
 use anyhow::Result;
 use clap::{App, ArgMatches};
-use rhq::Workspace;
+use rhq::ScanOptions;
 use std::path::PathBuf;
 
 #[derive(Debug)]
 pub struct ImportCommand {
     roots: Option<Vec<PathBuf>>,
-    depth: Option<usize>,
-    verbose: bool,
+    options: ScanOptions,
+    full: bool,
 }
 
 impl ImportCommand {
@@ -15,25 +16,31 @@ impl ImportCommand {
         app.about("Import existed repositories into management")
             .arg_from_usage("[roots]...      'Root directories contains for scanning'")
             .arg_from_usage("--depth=[depth] 'Maximal depth of entries for each base directory'")
-            .arg_from_usage("-v, --verbose   'Use verbose output'")
+            .arg_from_usage("--full          'Scan all directories, instead of only changed ones since the last import'")
+            .arg_from_usage("--nested        'Import repositories nested in other repositories (e.g. vendored ones)'")
+            .arg_from_usage("--submodules    'Import submodules of Git repositories'")
     }
 
     pub fn from_matches(m: &ArgMatches) -> ImportCommand {
         ImportCommand {
             roots: m.values_of("roots").map(|s| s.map(PathBuf::from).collect()),
-            depth: m.value_of("depth").and_then(|s| s.parse().ok()),
-            verbose: m.is_present("verbose"),
+            options: ScanOptions {
+                depth: m.value_of("depth").and_then(|s| s.parse().ok()),
+                nested: m.is_present("nested"),
+                submodules: m.is_present("submodules"),
+            },
+            full: m.is_present("full"),
         }
     }
 
-    pub fn run(self) -> Result<()> {
-        let mut workspace = Workspace::new()?.verbose_output(self.verbose);
+    pub fn run(self, globals: &GlobalOptions) -> Result<()> {
+        let mut workspace = globals.workspace()?;
 
         let roots = self
             .roots
             .unwrap_or_else(|| workspace.config().include_dirs.clone());
         for root in roots {
-            workspace.import_repositories(root, self.depth)?;
+            workspace.import_repositories(root, self.options, self.full)?;
         }
         workspace.save_cache()?;
 
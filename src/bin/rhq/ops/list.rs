@@ -1,7 +1,16 @@
+use super::{tag_arg, tags_of, GlobalOptions};
 use anyhow::Result;
+use chrono::Local;
 use clap::{App, Arg, ArgMatches};
-use rhq::Workspace;
-use std::str::FromStr;
+use rhq::{
+    printer::{Event, MessageFormat},
+    Link, Repository,
+};
+use std::{
+    cmp::{Ordering, Reverse},
+    io::{self, IsTerminal},
+    str::FromStr,
+};
 
 #[derive(Debug)]
 enum ListFormat {
@@ -21,34 +30,173 @@ impl FromStr for ListFormat {
     }
 }
 
+/// The order of listed repositories.
+#[derive(Debug)]
+enum SortKey {
+    Name,
+    Path,
+    Added,
+    Accessed,
+    Frecency,
+    Committed,
+    Size,
+}
+
+impl FromStr for SortKey {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "name" => Ok(SortKey::Name),
+            "path" => Ok(SortKey::Path),
+            "added" => Ok(SortKey::Added),
+            "accessed" => Ok(SortKey::Accessed),
+            "frecency" => Ok(SortKey::Frecency),
+            "committed" => Ok(SortKey::Committed),
+            "size" => Ok(SortKey::Size),
+            _ => Err(()),
+        }
+    }
+}
+
+impl SortKey {
+    /// Sort repositories, placing the most recent (or the largest) first.
+    ///
+    /// Repositories without the information are placed last.
+    fn sort(&self, repos: &mut [&Repository]) {
+        match self {
+            SortKey::Name => repos.sort_by(|a, b| a.name().cmp(b.name())),
+            SortKey::Path => repos.sort_by(|a, b| a.path().cmp(b.path())),
+            SortKey::Added => repos.sort_by_key(|repo| Reverse(repo.metadata().added_at)),
+            SortKey::Accessed => repos.sort_by_key(|repo| Reverse(repo.metadata().last_accessed)),
+            SortKey::Frecency => {
+                let now = Local::now();
+                repos.sort_by(|a, b| {
+                    let a = a.metadata().frecency(now);
+                    let b = b.metadata().frecency(now);
+                    b.partial_cmp(&a).unwrap_or(Ordering::Equal)
+                })
+            }
+            SortKey::Committed => repos.sort_by_key(|repo| Reverse(repo.metadata().last_commit_at)),
+            SortKey::Size => repos.sort_by_key(|repo| Reverse(repo.metadata().size)),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct ListCommand {
     format: ListFormat,
+    duplicates: bool,
+    sort: Option<SortKey>,
+    tags: Vec<String>,
 }
 
 impl ListCommand {
     pub fn app<'a, 'b: 'a>(app: App<'a, 'b>) -> App<'a, 'b> {
-        app.about("List local repositories managed by rhq").arg(
-            Arg::from_usage("--format=[format] 'List format'")
-                .possible_values(&["name", "fullpath"])
-                .default_value("fullpath"),
-        )
+        app.about("List local repositories managed by rhq")
+            .arg(
+                Arg::from_usage("--format=[format] 'List format'")
+                    .possible_values(&["name", "fullpath"])
+                    .default_value("fullpath"),
+            )
+            .arg_from_usage(
+                "--duplicates 'List only repositories cloned from the same remote, grouped by remote'",
+            )
+            .arg(
+                Arg::from_usage("--sort=[key] 'Sort repositories, most recent or largest first'")
+                    .possible_values(&["name", "path", "added", "accessed", "frecency", "committed", "size"]),
+            )
+            .arg(tag_arg())
     }
 
     pub fn from_matches(m: &ArgMatches) -> ListCommand {
         ListCommand {
             format: m.value_of("format").and_then(|s| s.parse().ok()).unwrap(),
+            duplicates: m.is_present("duplicates"),
+            sort: m.value_of("sort").and_then(|s| s.parse().ok()),
+            tags: tags_of(m),
         }
     }
 
-    pub fn run(self) -> Result<()> {
-        let workspace = Workspace::new()?;
-        workspace.for_each_repo(|repo| {
-            match self.format {
-                ListFormat::Name => println!("{}", repo.name()),
-                ListFormat::FullPath => println!("{}", repo.path_string()),
+    pub fn run(self, globals: &GlobalOptions) -> Result<()> {
+        let mut workspace = globals.workspace()?;
+        let is_json = globals.message_format() == MessageFormat::Json;
+        if self.duplicates {
+            for (remote, mut repositories) in workspace.duplicated_repositories()? {
+                repositories.retain(|repo| workspace.has_tags(repo, &self.tags));
+                if repositories.len() < 2 {
+                    continue;
+                }
+                if is_json {
+                    workspace.emit(Event::Duplicated {
+                        remote: &remote,
+                        repositories,
+                    });
+                    continue;
+                }
+                println!("{}", remote);
+                for repo in repositories {
+                    match self.format {
+                        ListFormat::Name => println!("    {}", repo.name()),
+                        ListFormat::FullPath => println!("    {}", repo.path_string()),
+                    }
+                }
+            }
+            return Ok(());
+        }
+
+        if let Some(SortKey::Committed) | Some(SortKey::Size) = self.sort {
+            // These are collected only here, since it requires spawning VCS processes or walking all the files.
+            let paths: Vec<_> = workspace
+                .repositories()
+                .ok_or(rhq::Error::CacheNotInitialized)?
+                .iter()
+                .filter(|repo| workspace.has_tags(repo, &self.tags))
+                .map(|repo| repo.path().to_owned())
+                .collect();
+            for path in paths {
+                let result = match self.sort {
+                    Some(SortKey::Size) => workspace.measure_size(&path).map(drop),
+                    _ => workspace.collect_metadata(&path),
+                };
+                if let Err(e) = result {
+                    workspace.emit(Event::Warning {
+                        message: format!("{} {}", e, path.display()),
+                    });
+                }
             }
-            Ok(())
-        })
+        }
+
+        let mut repos: Vec<&Repository> = workspace
+            .repositories()
+            .ok_or(rhq::Error::CacheNotInitialized)?
+            .iter()
+            .filter(|repo| workspace.has_tags(repo, &self.tags))
+            .collect();
+        if let Some(ref sort) = self.sort {
+            sort.sort(&mut repos);
+        }
+        // Linked worktrees and submodules are marked only on terminal, to keep the output pipeable.
+        let mark_links = io::stdout().is_terminal();
+        for repo in repos {
+            if is_json {
+                workspace.emit(Event::Repository { repository: repo });
+                continue;
+            }
+            let entry = match self.format {
+                ListFormat::Name => repo.name().to_owned(),
+                ListFormat::FullPath => repo.path_string(),
+            };
+            match repo.link() {
+                Some(Link::Worktree { main }) if mark_links => {
+                    println!("{}  (worktree of {})", entry, main.display())
+                }
+                Some(Link::Submodule { superproject }) if mark_links => {
+                    println!("{}  (submodule of {})", entry, superproject.display())
+                }
+                _ => println!("{}", entry),
+            }
+        }
+        Ok(())
     }
 }
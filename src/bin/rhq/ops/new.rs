@@ -1,10 +1,7 @@
+use super::GlobalOptions;
 use anyhow::Result;
 use clap::{App, Arg, ArgMatches};
-use rhq::{
-    query::Query,
-    vcs::{Vcs, POSSIBLE_VCS},
-    Workspace,
-};
+use rhq::{query::Query, vcs::Vcs};
 use std::path::Path;
 
 #[derive(Debug)]
@@ -18,13 +15,18 @@ pub struct NewCommand<'a> {
 impl<'a> NewCommand<'a> {
     pub fn app<'b: 'a>(app: App<'a, 'b>) -> App<'a, 'b> {
         app.about("Create a new repository and add it into management")
-            .arg_from_usage("<query>           'Path of target repository, or URL-like pattern'")
+            .arg(
+                Arg::from_usage(
+                    "<query>           'Path of target repository, or URL-like pattern'",
+                )
+                .validator(super::validate_query),
+            )
             .arg_from_usage(
                 "--root=[root]    'Path to determine the destination of new repository'",
             )
             .arg(
                 Arg::from_usage("--vcs=[vcs] 'Used Version Control System'")
-                    .possible_values(POSSIBLE_VCS)
+                    .validator(super::validate_vcs)
                     .default_value("git"),
             )
             .arg_from_usage("-s, --ssh        'Use SSH protocol instead of HTTP(s)'")
@@ -39,8 +41,8 @@ impl<'a> NewCommand<'a> {
         }
     }
 
-    pub fn run(self) -> Result<()> {
-        let mut workspace = Workspace::new()?;
+    pub fn run(self, globals: &GlobalOptions) -> Result<()> {
+        let mut workspace = globals.workspace()?;
         if let Some(root) = self.root {
             workspace.set_root_dir(root);
         }
@@ -0,0 +1,64 @@
+use super::{tag_arg, tags_of, GlobalOptions};
+use anyhow::Result;
+use clap::{App, ArgMatches};
+use rhq::printer::{Event, MessageFormat};
+
+#[derive(Debug)]
+pub struct PathCommand {
+    keywords: Vec<String>,
+    list: bool,
+    tags: Vec<String>,
+}
+
+impl PathCommand {
+    pub fn app<'a, 'b: 'a>(app: App<'a, 'b>) -> App<'a, 'b> {
+        app.about("Print the path of the most frecent repository matching the keywords")
+            .arg_from_usage("[keywords]... 'Keywords to match against the path of repositories'")
+            .arg_from_usage(
+                "-l, --list 'List all the matching repositories in order of rank, without recording an access'",
+            )
+            .arg(tag_arg())
+    }
+
+    pub fn from_matches(m: &ArgMatches) -> PathCommand {
+        PathCommand {
+            keywords: m
+                .values_of("keywords")
+                .map(|s| s.map(ToOwned::to_owned).collect())
+                .unwrap_or_default(),
+            list: m.is_present("list"),
+            tags: tags_of(m),
+        }
+    }
+
+    pub fn run(self, globals: &GlobalOptions) -> Result<()> {
+        let mut workspace = globals.workspace()?;
+        let is_json = globals.message_format() == MessageFormat::Json;
+        let mut ranked = workspace.ranked_repositories(&self.keywords)?;
+        ranked.retain(|repo| workspace.has_tags(repo, &self.tags));
+
+        if self.list {
+            for repo in ranked {
+                if is_json {
+                    workspace.emit(Event::Repository { repository: repo });
+                } else {
+                    println!("{}", repo.path_string());
+                }
+            }
+            return Ok(());
+        }
+
+        let repo = ranked.first().ok_or_else(|| rhq::Error::NoMatch {
+            keywords: self.keywords.join(" "),
+        })?;
+        if is_json {
+            workspace.emit(Event::Repository { repository: repo });
+        } else {
+            println!("{}", repo.path_string());
+        }
+        let path = repo.path().to_owned();
+        workspace.touch_repository(&path)?;
+        workspace.save_cache()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,133 @@
+use super::{tag_arg, tags_of, GlobalOptions};
+use crate::exit_code::PartialFailure;
+use anyhow::Result;
+use clap::{App, Arg, ArgMatches};
+use glob::Pattern;
+use rhq::{printer::Event, Remote};
+use std::path::PathBuf;
+
+#[derive(Debug)]
+pub struct SetUrlCommand {
+    patterns: Vec<Pattern>,
+    ssh: Option<bool>,
+    host: Option<String>,
+    dry_run: bool,
+    tags: Vec<String>,
+}
+
+impl SetUrlCommand {
+    pub fn app<'a, 'b: 'a>(app: App<'a, 'b>) -> App<'a, 'b> {
+        app.about("Rewrite remote URLs of managed repositories")
+            .arg(
+                Arg::from_usage("[patterns]...   'Glob patterns of remotes to be rewritten (e.g. github.com/owner/*)'")
+                    .validator(|s| Pattern::new(&s).map(drop).map_err(|e| e.to_string())),
+            )
+            .arg(
+                Arg::from_usage("--ssh           'Switch to SSH protocol'")
+                    .conflicts_with("https")
+                    .required_unless_one(&["https", "host"]),
+            )
+            .arg_from_usage("--https         'Switch to HTTPS protocol'")
+            .arg_from_usage("--host=[host]   'Replace the host name of remotes'")
+            .arg_from_usage("-n, --dry-run   'Show planned changes without rewriting'")
+            .arg(tag_arg())
+    }
+
+    pub fn from_matches(m: &ArgMatches) -> SetUrlCommand {
+        SetUrlCommand {
+            patterns: m
+                .values_of("patterns")
+                .map(|s| s.map(|s| Pattern::new(s).unwrap()).collect())
+                .unwrap_or_default(),
+            ssh: match (m.is_present("ssh"), m.is_present("https")) {
+                (true, _) => Some(true),
+                (_, true) => Some(false),
+                _ => None,
+            },
+            host: m.value_of("host").map(ToOwned::to_owned),
+            dry_run: m.is_present("dry-run"),
+            tags: tags_of(m),
+        }
+    }
+
+    pub fn run(self, globals: &GlobalOptions) -> Result<()> {
+        let mut workspace = globals.workspace()?;
+
+        let mut plans: Vec<(PathBuf, Remote)> = vec![];
+        workspace.for_each_repo(|repo| {
+            if !workspace.has_tags(repo, &self.tags) {
+                return Ok(());
+            }
+            let remote = match repo.remote() {
+                Some(remote) => remote,
+                None => return Ok(()),
+            };
+            let normalized = remote.normalized();
+            if !self.patterns.is_empty() && self.patterns.iter().all(|p| !p.matches(&normalized)) {
+                return Ok(());
+            }
+            let new_remote = match remote.rewrite(self.ssh, self.host.as_deref()) {
+                Ok(new_remote) => new_remote,
+                Err(e) => {
+                    workspace.emit(Event::Warning {
+                        message: format!("Skipped: {} ({})", repo.path_string(), e),
+                    });
+                    return Ok(());
+                }
+            };
+            if new_remote.url() != remote.url() {
+                workspace.emit(Event::RemoteChanged {
+                    path: repo.path(),
+                    from: remote.url(),
+                    to: new_remote.url(),
+                });
+                plans.push((repo.path().to_owned(), new_remote));
+            }
+            Ok(())
+        })?;
+
+        if self.dry_run {
+            return Ok(());
+        }
+
+        let mut num_failed = 0;
+        for (path, remote) in plans {
+            if let Err(e) = workspace.set_remote_url(&path, remote) {
+                workspace.emit(Event::Warning {
+                    message: format!("Failed: {} ({})", path.display(), e),
+                });
+                num_failed += 1;
+            }
+        }
+        workspace.save_cache()?;
+
+        if num_failed > 0 {
+            return Err(PartialFailure {
+                operation: "rewrite",
+                failed: num_failed,
+            }
+            .into());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::ErrorKind;
+
+    #[test]
+    fn reject_invalid_patterns() {
+        let app = || SetUrlCommand::app(App::new("set-url"));
+        let err = app()
+            .get_matches_from_safe(["set-url", "--ssh", "github.com/[foo"])
+            .unwrap_err();
+        assert_eq!(err.kind, ErrorKind::ValueValidation);
+
+        let m = app()
+            .get_matches_from_safe(["set-url", "--ssh", "github.com/foo/*"])
+            .unwrap();
+        assert_eq!(SetUrlCommand::from_matches(&m).patterns.len(), 1);
+    }
+}
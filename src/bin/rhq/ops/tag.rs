@@ -0,0 +1,104 @@
+use super::GlobalOptions;
+use anyhow::Result;
+use clap::{App, AppSettings, ArgMatches, SubCommand};
+use rhq::printer::Event;
+
+#[derive(Debug)]
+enum TagAction {
+    Add { repo: String, tags: Vec<String> },
+    Remove { repo: String, tags: Vec<String> },
+    List,
+}
+
+#[derive(Debug)]
+pub struct TagCommand {
+    action: TagAction,
+}
+
+impl TagCommand {
+    pub fn app<'a, 'b: 'a>(app: App<'a, 'b>) -> App<'a, 'b> {
+        let repo_arg = "<repo> 'Location in the repository, or trailing components of its path (e.g. owner/name)'";
+        app.about("Manage tags attached to repositories")
+            .setting(AppSettings::SubcommandRequiredElseHelp)
+            .subcommand(
+                SubCommand::with_name("add")
+                    .about("Attach tags to a repository")
+                    .arg_from_usage(repo_arg)
+                    .arg_from_usage("<tags>... 'Tags to attach'"),
+            )
+            .subcommand(
+                SubCommand::with_name("remove")
+                    .about("Detach tags from a repository")
+                    .alias("rm")
+                    .arg_from_usage(repo_arg)
+                    .arg_from_usage("<tags>... 'Tags to detach'"),
+            )
+            .subcommand(
+                SubCommand::with_name("list")
+                    .about("List tags and groups with the number of repositories")
+                    .alias("ls"),
+            )
+    }
+
+    pub fn from_matches(m: &ArgMatches) -> TagCommand {
+        let repo_and_tags = |m: &ArgMatches| {
+            let repo = m.value_of("repo").unwrap().to_owned();
+            let tags = m
+                .values_of("tags")
+                .unwrap()
+                .map(ToOwned::to_owned)
+                .collect();
+            (repo, tags)
+        };
+        let action = match m.subcommand() {
+            ("add", Some(m)) => {
+                let (repo, tags) = repo_and_tags(m);
+                TagAction::Add { repo, tags }
+            }
+            ("remove", Some(m)) => {
+                let (repo, tags) = repo_and_tags(m);
+                TagAction::Remove { repo, tags }
+            }
+            ("list", Some(..)) => TagAction::List,
+            _ => unreachable!(),
+        };
+        TagCommand { action }
+    }
+
+    pub fn run(self, globals: &GlobalOptions) -> Result<()> {
+        let mut workspace = globals.workspace()?;
+        match self.action {
+            TagAction::Add { repo, tags } => {
+                let path = workspace.find_repository(&repo)?.path().to_owned();
+                for tag in tags {
+                    if workspace.add_tag(&path, &tag)? {
+                        workspace.print(format_args!("Tagged {}: {}\n", path.display(), tag));
+                    }
+                }
+                workspace.save_cache()?;
+            }
+            TagAction::Remove { repo, tags } => {
+                let path = workspace.find_repository(&repo)?.path().to_owned();
+                for tag in tags {
+                    if workspace.remove_tag(&path, &tag)? {
+                        workspace.print(format_args!("Untagged {}: {}\n", path.display(), tag));
+                    } else if workspace.config().groups.contains_key(&tag) {
+                        workspace.emit(Event::Warning {
+                            message: format!(
+                                "{} is a group defined in the configuration, which cannot be detached",
+                                tag
+                            ),
+                        });
+                    }
+                }
+                workspace.save_cache()?;
+            }
+            TagAction::List => {
+                for (name, repositories) in workspace.all_tags()? {
+                    workspace.emit(Event::Tag { name, repositories });
+                }
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,81 @@
+use super::{confirm, tag_arg, tags_of, GlobalOptions};
+use crate::exit_code::PartialFailure;
+use anyhow::Result;
+use clap::{App, ArgMatches};
+use rhq::printer::Event;
+use std::path::PathBuf;
+
+#[derive(Debug)]
+pub struct TidyCommand {
+    dry_run: bool,
+    yes: bool,
+    tags: Vec<String>,
+}
+
+impl TidyCommand {
+    pub fn app<'a, 'b: 'a>(app: App<'a, 'b>) -> App<'a, 'b> {
+        app.about("Move managed repositories into the location determined from their remotes")
+            .arg_from_usage(
+                "-n, --dry-run   'Show planned moves without touching any repositories'",
+            )
+            .arg_from_usage("-y, --yes       'Move repositories without confirmation'")
+            .arg(tag_arg())
+    }
+
+    pub fn from_matches(m: &ArgMatches) -> TidyCommand {
+        TidyCommand {
+            dry_run: m.is_present("dry-run"),
+            yes: m.is_present("yes"),
+            tags: tags_of(m),
+        }
+    }
+
+    pub fn run(self, globals: &GlobalOptions) -> Result<()> {
+        let mut workspace = globals.workspace()?;
+
+        let plans: Vec<(PathBuf, PathBuf)> = workspace
+            .misplaced_repositories()?
+            .into_iter()
+            .filter(|(repo, _)| workspace.has_tags(repo, &self.tags))
+            .map(|(repo, dest)| (repo.path().to_owned(), dest))
+            .collect();
+
+        let mut num_failed = 0;
+        for (path, dest) in plans {
+            workspace.emit(Event::RepositoryMoved {
+                from: &path,
+                to: &dest,
+            });
+            if dest.exists() {
+                workspace.emit(Event::Warning {
+                    message: format!("Skipped: {} already exists", dest.display()),
+                });
+                if !self.dry_run {
+                    num_failed += 1;
+                }
+                continue;
+            }
+            if self.dry_run || !(self.yes || confirm("Move this repository?")?) {
+                continue;
+            }
+            if let Err(e) = workspace.move_repository(&path, &dest) {
+                workspace.emit(Event::Warning {
+                    message: format!("Failed: {} ({})", path.display(), e),
+                });
+                num_failed += 1;
+            }
+        }
+        if !self.dry_run {
+            workspace.save_cache()?;
+        }
+
+        if num_failed > 0 {
+            return Err(PartialFailure {
+                operation: "move",
+                failed: num_failed,
+            }
+            .into());
+        }
+        Ok(())
+    }
+}
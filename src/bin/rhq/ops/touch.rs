@@ -0,0 +1,33 @@
+use super::GlobalOptions;
+use anyhow::Result;
+use clap::{App, ArgMatches};
+use std::{env, path::PathBuf};
+
+#[derive(Debug)]
+pub struct TouchCommand {
+    path: Option<PathBuf>,
+}
+
+impl TouchCommand {
+    pub fn app<'a, 'b: 'a>(app: App<'a, 'b>) -> App<'a, 'b> {
+        app.about("Record an access to the repository, to rank it higher in `rhq path`")
+            .arg_from_usage("[path] 'Location in the repository (default: current directory)'")
+    }
+
+    pub fn from_matches(m: &ArgMatches) -> TouchCommand {
+        TouchCommand {
+            path: m.value_of("path").map(PathBuf::from),
+        }
+    }
+
+    pub fn run(self, globals: &GlobalOptions) -> Result<()> {
+        let path = self
+            .path
+            .unwrap_or_else(|| env::current_dir().expect("env::current_dir()"));
+
+        let mut workspace = globals.workspace()?;
+        workspace.touch_repository(&path)?;
+        workspace.save_cache()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,34 @@
+use super::GlobalOptions;
+use anyhow::Result;
+use clap::{App, Arg, ArgMatches};
+use std::time::Duration;
+
+#[derive(Debug)]
+pub struct WatchCommand {
+    debounce: Duration,
+}
+
+impl WatchCommand {
+    pub fn app<'a, 'b: 'a>(app: App<'a, 'b>) -> App<'a, 'b> {
+        app.about("Watch directories and keep the list of repositories up to date")
+            .arg(
+                Arg::from_usage(
+                    "--debounce=[millis] 'Time to wait for subsequent events before updating the cache'",
+                )
+                .default_value("500")
+                .validator(|s| s.parse::<u64>().map(drop).map_err(|e| e.to_string())),
+            )
+    }
+
+    pub fn from_matches(m: &ArgMatches) -> WatchCommand {
+        WatchCommand {
+            debounce: Duration::from_millis(m.value_of("debounce").unwrap().parse().unwrap()),
+        }
+    }
+
+    pub fn run(self, globals: &GlobalOptions) -> Result<()> {
+        let mut workspace = globals.workspace()?;
+        workspace.watch(self.debounce)?;
+        Ok(())
+    }
+}
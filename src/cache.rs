@@ -1,15 +1,31 @@
 //! Defines cache file format
 
+use crate::error::{Error, Result};
 use crate::repository::Repository;
-use anyhow::Result;
+use crate::scan::ScanState;
 use chrono::{DateTime, Local};
 use serde::{Deserialize, Serialize};
-use std::{fs::OpenOptions, path::Path};
+use std::{
+    collections::BTreeMap,
+    ffi::OsString,
+    fs::{self, File, OpenOptions},
+    io,
+    path::{Path, PathBuf},
+};
 
 // inner representation of cache format.
-#[derive(Default, Debug, Serialize, Deserialize)]
+#[derive(Clone, Default, Debug, Serialize, Deserialize)]
 pub struct CacheData {
     pub repositories: Vec<Repository>,
+    /// The metadata of the last scan of each directory imported.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub scans: BTreeMap<PathBuf, ScanState>,
+}
+
+/// The exclusive lock of the cache file among processes, which is released when dropped.
+#[derive(Debug)]
+pub struct CacheLock {
+    _file: File,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -22,8 +38,10 @@ impl Cache {
     pub fn new(cache_path: &Path) -> Result<Self> {
         if cache_path.exists() {
             let mut file = OpenOptions::new().read(true).open(cache_path)?;
-            let cache = serde_json::from_reader(&mut file)?;
-            Ok(cache)
+            serde_json::from_reader(&mut file).map_err(|source| Error::CacheCorrupt {
+                path: cache_path.into(),
+                source,
+            })
         } else {
             Ok(Cache {
                 timestamp: Local::now(),
@@ -43,10 +61,89 @@ impl Cache {
         self.inner.as_mut().unwrap()
     }
 
+    /// Apply the changes from `base` to `ours` onto this cache, which is reloaded from the file.
+    ///
+    /// Repositories and scans are compared by their paths, so that the entries changed by
+    /// other processes (e.g. `rhq watch`) in the meantime are preserved unless `ours` changed them too.
+    pub fn merge(&mut self, base: Option<&CacheData>, ours: &CacheData) {
+        let empty = CacheData::default();
+        let base = base.unwrap_or(&empty);
+        let theirs = self.get_mut();
+
+        let base_repos: BTreeMap<&Path, &Repository> = base
+            .repositories
+            .iter()
+            .map(|repo| (repo.path(), repo))
+            .collect();
+        let our_repos: BTreeMap<&Path, &Repository> = ours
+            .repositories
+            .iter()
+            .map(|repo| (repo.path(), repo))
+            .collect();
+        theirs.repositories.retain(|repo| {
+            !base_repos.contains_key(repo.path()) || our_repos.contains_key(repo.path())
+        });
+        for repo in &ours.repositories {
+            if base_repos.get(repo.path()) == Some(&repo) {
+                continue;
+            }
+            match theirs
+                .repositories
+                .iter_mut()
+                .find(|r| r.path() == repo.path())
+            {
+                Some(r) => *r = repo.clone(),
+                None => theirs.repositories.push(repo.clone()),
+            }
+        }
+
+        theirs
+            .scans
+            .retain(|root, _| !base.scans.contains_key(root) || ours.scans.contains_key(root));
+        for (root, state) in &ours.scans {
+            if base.scans.get(root) != Some(state) {
+                theirs.scans.insert(root.clone(), state.clone());
+            }
+        }
+    }
+
+    /// Acquire the lock of the cache file, waiting until other processes release it.
+    pub fn lock(cache_path: &Path) -> Result<CacheLock> {
+        if let Some(parent) = cache_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(lock_path(cache_path))?;
+        file.lock()?;
+        Ok(CacheLock { _file: file })
+    }
+
+    /// Returns whether `path` is the cache file or one of the files used to write it.
+    pub fn is_cache_file(cache_path: &Path, path: &Path) -> bool {
+        match (cache_path.file_name(), path.file_name()) {
+            (Some(cache), Some(name)) => {
+                path.parent() == cache_path.parent()
+                    && name
+                        .to_string_lossy()
+                        .starts_with(&*cache.to_string_lossy())
+            }
+            _ => false,
+        }
+    }
+
     pub fn dump(&mut self, cache_path: &Path) -> Result<()> {
         self.timestamp = Local::now();
         crate::util::write_content(cache_path, |f| {
-            serde_json::to_writer_pretty(f, &self).map_err(Into::into)
+            serde_json::to_writer_pretty(f, &self).map_err(|e| Error::from(io::Error::from(e)))
         })
     }
 }
+
+fn lock_path(cache_path: &Path) -> PathBuf {
+    let mut path = OsString::from(cache_path.as_os_str());
+    path.push(".lock");
+    PathBuf::from(path)
+}
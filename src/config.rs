@@ -1,10 +1,13 @@
 //! Defines configuration file format.
 
-use anyhow::{anyhow, Result};
+use crate::error::{Error, Result};
+use crate::vcs::{self, Backend, Vcs, VcsConfig};
 use glob::Pattern;
 use lazy_static::lazy_static;
 use serde::Deserialize;
 use std::{
+    borrow::Cow,
+    collections::{BTreeMap, HashMap},
     fs,
     io::Read,
     ops::{Deref, DerefMut},
@@ -17,6 +20,9 @@ lazy_static! {
         .expect("failed to determine the configuration path");
 }
 
+/// Directories which usually contain a large number of files generated by build tools.
+const DEFAULT_SKIP_DIRS: &[&str] = &["node_modules", "target", ".venv"];
+
 /// configuration load from config files
 #[derive(Deserialize)]
 struct RawConfigData {
@@ -24,6 +30,18 @@ struct RawConfigData {
     default_host: Option<String>,
     includes: Option<Vec<String>>,
     excludes: Option<Vec<String>>,
+    skip_dirs: Option<Vec<String>>,
+    groups: Option<BTreeMap<String, Vec<String>>>,
+    vcs: Option<HashMap<String, RawVcsConfig>>,
+}
+
+/// `[vcs.<name>]` sections
+#[derive(Deserialize)]
+struct RawVcsConfig {
+    backend: Option<Backend>,
+    command: Option<String>,
+    clone_args: Option<Vec<String>>,
+    env: Option<BTreeMap<String, String>>,
 }
 
 #[derive(Debug)]
@@ -32,6 +50,13 @@ pub struct ConfigData {
     pub host: String,
     pub include_dirs: Vec<PathBuf>,
     pub exclude_patterns: Vec<Pattern>,
+    /// Names of directories which are never scanned.
+    pub skip_dirs: Vec<String>,
+    /// Groups of repositories, selected by glob patterns of their locations or remotes.
+    pub groups: BTreeMap<String, Vec<Pattern>>,
+    pub vcs: HashMap<Vcs, VcsConfig>,
+    /// Whether the standard output of VCS executables is redirected into the standard error.
+    pub stdout_to_stderr: bool,
 }
 
 impl ConfigData {
@@ -44,7 +69,7 @@ impl ConfigData {
             .as_deref()
             .unwrap_or(&[])
             .iter()
-            .filter_map(|root| crate::util::make_path_buf(&root).ok())
+            .filter_map(|root| crate::util::make_path_buf(root).ok())
             .collect();
 
         let exclude_patterns = raw
@@ -60,15 +85,92 @@ impl ConfigData {
             })
             .collect();
 
+        let skip_dirs = raw.skip_dirs.unwrap_or_else(|| {
+            DEFAULT_SKIP_DIRS
+                .iter()
+                .map(|&name| name.to_owned())
+                .collect()
+        });
+
+        let groups = raw
+            .groups
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(name, patterns)| {
+                let patterns = patterns
+                    .iter()
+                    .filter_map(|pattern| {
+                        ::shellexpand::full(pattern)
+                            .ok()
+                            .map(|pattern| pattern.replace(r"\", "/"))
+                            .and_then(|pattern| Pattern::new(&pattern).ok())
+                    })
+                    .collect();
+                (name, patterns)
+            })
+            .collect();
+
         let host = raw.default_host.unwrap_or_else(|| "github.com".to_owned());
 
+        let mut raw_vcs = raw.vcs.unwrap_or_default();
+        let vcs = vcs::backends()
+            .iter()
+            .map(|backend| backend.vcs())
+            .map(|vcs| {
+                let mut config = VcsConfig::new(vcs);
+                if let Some(raw) = raw_vcs.remove(vcs.name()) {
+                    match raw.backend {
+                        Some(backend) => config.backend = backend,
+                        // The library neither runs the executable nor sets the environment variables.
+                        None if raw.command.is_some() || raw.env.is_some() => {
+                            config.backend = Backend::Command
+                        }
+                        None => {}
+                    }
+                    if let Some(command) = raw.command {
+                        config.command = ::shellexpand::full(&command)
+                            .map(|command| command.into_owned())
+                            .unwrap_or(command);
+                    }
+                    config.clone_args = raw.clone_args.unwrap_or_default();
+                    config.env = raw.env.unwrap_or_default();
+                }
+                (vcs, config)
+            })
+            .collect();
+
         Ok(Self {
             root_dir,
             host,
             include_dirs,
             exclude_patterns,
+            skip_dirs,
+            groups,
+            vcs,
+            stdout_to_stderr: false,
         })
     }
+
+    /// Returns the settings of executable of `vcs`.
+    ///
+    /// The default settings are returned for backends registered after loading the configuration.
+    pub fn vcs(&self, vcs: Vcs) -> Cow<'_, VcsConfig> {
+        match self.vcs.get(&vcs) {
+            Some(config) => Cow::Borrowed(config),
+            None => Cow::Owned(VcsConfig {
+                stdout_to_stderr: self.stdout_to_stderr,
+                ..VcsConfig::new(vcs)
+            }),
+        }
+    }
+
+    /// Redirect the standard output of VCS executables into the standard error, or not.
+    pub fn redirect_stdout(&mut self, stdout_to_stderr: bool) {
+        self.stdout_to_stderr = stdout_to_stderr;
+        for config in self.vcs.values_mut() {
+            config.stdout_to_stderr = stdout_to_stderr;
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -81,15 +183,17 @@ impl Config {
     pub fn new(config_path: Option<&Path>) -> Result<Self> {
         let config_path: &Path = config_path.unwrap_or_else(|| &*CONFIG_PATH);
         if !config_path.is_file() {
-            return Err(anyhow!(
-                "Failed to load configuration file (config_path = {})",
-                config_path.display()
-            ));
+            return Err(Error::ConfigNotFound {
+                path: config_path.into(),
+            });
         }
 
         let mut content = String::new();
         fs::File::open(config_path)?.read_to_string(&mut content)?;
-        let data = ::toml::from_str(&content)?;
+        let data = ::toml::from_str(&content).map_err(|source| Error::InvalidConfig {
+            path: config_path.into(),
+            source,
+        })?;
 
         Ok(Config {
             path: config_path.into(),
@@ -97,6 +201,11 @@ impl Config {
         })
     }
 
+    /// Returns the path of loaded configuration file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
     pub fn cache_dir(&self) -> PathBuf {
         self.root_dir.join(".cache.json")
     }
@@ -117,3 +226,94 @@ impl DerefMut for Config {
         &mut self.data
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn vcs_sections() {
+        let raw: RawConfigData = ::toml::from_str(
+            r#"
+            [vcs.git]
+            command = "/opt/git/bin/git"
+            clone_args = ["--filter=blob:none"]
+            env = { GIT_SSH_COMMAND = "ssh -i ~/.ssh/id_work" }
+            "#,
+        )
+        .unwrap();
+        let config = ConfigData::from_raw(raw).unwrap();
+
+        let git = config.vcs(Vcs::Git);
+        assert_eq!(git.command, "/opt/git/bin/git");
+        assert_eq!(git.clone_args, ["--filter=blob:none"]);
+        assert_eq!(git.env["GIT_SSH_COMMAND"], "ssh -i ~/.ssh/id_work");
+
+        let hg = config.vcs(Vcs::Hg);
+        assert_eq!(hg.command, "hg");
+        assert!(hg.clone_args.is_empty());
+    }
+
+    #[test]
+    fn groups() {
+        let raw: RawConfigData = ::toml::from_str(
+            r#"
+            [groups]
+            work = ["github.com/our-org/*", "gitlab.example.com/**"]
+            "#,
+        )
+        .unwrap();
+        let config = ConfigData::from_raw(raw).unwrap();
+        assert_eq!(config.groups.len(), 1);
+        assert!(config.groups["work"][0].matches("github.com/our-org/api"));
+        assert!(!config.groups["work"][0].matches("github.com/other/api"));
+    }
+
+    #[test]
+    fn redirect_stdout() {
+        let raw: RawConfigData = ::toml::from_str("[vcs.git]\ncommand = \"git\"").unwrap();
+        let mut config = ConfigData::from_raw(raw).unwrap();
+        assert!(!config.vcs(Vcs::Git).stdout_to_stderr);
+
+        config.redirect_stdout(true);
+        assert!(config.vcs(Vcs::Git).stdout_to_stderr);
+        assert!(config.vcs(Vcs::Custom("unregistered")).stdout_to_stderr);
+    }
+
+    #[test]
+    fn vcs_backend() {
+        let err = match ::toml::from_str::<RawConfigData>("[vcs.git]\nbackend = \"libary\"") {
+            Ok(..) => panic!("an invalid backend is accepted"),
+            Err(err) => err,
+        };
+        assert!(
+            err.to_string().contains("libary is invalid backend"),
+            "{}",
+            err
+        );
+
+        let raw: RawConfigData = ::toml::from_str(
+            r#"
+            [vcs.git]
+            env = { GIT_SSH_COMMAND = "ssh -i ~/.ssh/id_work" }
+            [vcs.hg]
+            backend = "command"
+            "#,
+        )
+        .unwrap();
+        let config = ConfigData::from_raw(raw).unwrap();
+        assert_eq!(config.vcs(Vcs::Git).backend, Backend::Command);
+        assert_eq!(config.vcs(Vcs::Hg).backend, Backend::Command);
+    }
+
+    #[test]
+    fn skip_dirs() {
+        let raw: RawConfigData = ::toml::from_str("").unwrap();
+        let config = ConfigData::from_raw(raw).unwrap();
+        assert_eq!(config.skip_dirs, DEFAULT_SKIP_DIRS);
+
+        let raw: RawConfigData = ::toml::from_str(r#"skip_dirs = ["vendor"]"#).unwrap();
+        let config = ConfigData::from_raw(raw).unwrap();
+        assert_eq!(config.skip_dirs, ["vendor"]);
+    }
+}
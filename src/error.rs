@@ -0,0 +1,303 @@
+//! Defines the error type of this crate.
+
+use crate::vcs::{Vcs, Version};
+use std::{
+    error, fmt, io,
+    path::{Path, PathBuf},
+};
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// The error type of operations provided by this crate.
+///
+/// The variants are stable, and are expected to be matched by callers
+/// to determine how to recover from the failure.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// The configuration file is not found.
+    ConfigNotFound { path: PathBuf },
+    /// The configuration file is malformed.
+    InvalidConfig {
+        path: PathBuf,
+        source: toml::de::Error,
+    },
+    /// The cache file is malformed.
+    CacheCorrupt {
+        path: PathBuf,
+        source: serde_json::Error,
+    },
+    /// The cache has not been created yet.
+    CacheNotInitialized,
+    /// The executable of VCS is not found.
+    VcsNotFound {
+        command: String,
+        vcs: Option<Vcs>,
+        source: io::Error,
+    },
+    /// The executable of VCS is older than the minimum supported version.
+    VcsTooOld {
+        vcs: Vcs,
+        version: Version,
+        required: Version,
+    },
+    /// The VCS command exited with a failure status.
+    VcsFailed { command: String, code: Option<i32> },
+    /// The remote repository does not exist.
+    RemoteNotFound { url: String },
+    /// The VCS of remote repository cannot be determined from its URL.
+    VcsUndetermined { url: String, candidates: Vec<Vcs> },
+    /// No backend is registered for the VCS.
+    VcsNotRegistered { vcs: Vcs },
+    /// The operation is not supported by the VCS.
+    Unsupported { vcs: Vcs, operation: &'static str },
+    /// The destination of repository has already existed.
+    DestinationExists { path: PathBuf },
+    /// The repository is not managed by rhq.
+    NotManaged { path: PathBuf },
+    /// No managed repository matches the keywords.
+    NoMatch { keywords: String },
+    /// The repository has changes which exist only in the local, and cannot be removed.
+    LocalChanges { path: PathBuf, reason: String },
+    /// The query or remote URL cannot be interpreted.
+    InvalidQuery { query: String, reason: String },
+    /// The path cannot be interpreted.
+    InvalidPath { path: String, reason: String },
+    /// An error reported by the Git library.
+    #[cfg(feature = "git2")]
+    Git { source: git2::Error },
+    /// Failed to parse an URL.
+    InvalidUrl { source: url::ParseError },
+    /// Failed to watch the file system.
+    Watch { source: notify::Error },
+    /// An I/O error.
+    Io { source: io::Error },
+}
+
+impl Error {
+    /// Returns a short, stable identifier of the error kind, e.g. `"vcs-failed"`.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Error::ConfigNotFound { .. } => "config-not-found",
+            Error::InvalidConfig { .. } => "invalid-config",
+            Error::CacheCorrupt { .. } => "cache-corrupt",
+            Error::CacheNotInitialized => "cache-not-initialized",
+            Error::VcsNotFound { .. } => "vcs-not-found",
+            Error::VcsTooOld { .. } => "vcs-too-old",
+            Error::VcsFailed { .. } => "vcs-failed",
+            Error::RemoteNotFound { .. } => "remote-not-found",
+            Error::VcsUndetermined { .. } => "vcs-undetermined",
+            Error::VcsNotRegistered { .. } => "vcs-not-registered",
+            Error::Unsupported { .. } => "unsupported",
+            Error::DestinationExists { .. } => "destination-exists",
+            Error::NotManaged { .. } => "not-managed",
+            Error::NoMatch { .. } => "no-match",
+            Error::LocalChanges { .. } => "local-changes",
+            Error::InvalidQuery { .. } => "invalid-query",
+            Error::InvalidPath { .. } => "invalid-path",
+            #[cfg(feature = "git2")]
+            Error::Git { .. } => "git",
+            Error::InvalidUrl { .. } => "invalid-url",
+            Error::Watch { .. } => "watch",
+            Error::Io { .. } => "io",
+        }
+    }
+
+    pub(crate) fn invalid_query<Q, R>(query: Q, reason: R) -> Self
+    where
+        Q: Into<String>,
+        R: Into<String>,
+    {
+        Error::InvalidQuery {
+            query: query.into(),
+            reason: reason.into(),
+        }
+    }
+
+    /// Make an error from the failure of spawning a command.
+    pub(crate) fn spawn(command: &str, source: io::Error) -> Self {
+        match source.kind() {
+            io::ErrorKind::NotFound => Error::VcsNotFound {
+                command: command.to_owned(),
+                vcs: Path::new(command)
+                    .file_stem()
+                    .and_then(|stem| stem.to_str())
+                    .and_then(|stem| stem.parse().ok()),
+                source,
+            },
+            _ => Error::Io { source },
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::ConfigNotFound { path } => write!(
+                f,
+                "Failed to load configuration file (config_path = {})",
+                path.display()
+            ),
+            Error::InvalidConfig { path, .. } => {
+                write!(f, "invalid configuration file: {}", path.display())
+            }
+            Error::CacheCorrupt { path, .. } => {
+                write!(f, "the cache file is corrupted: {}", path.display())
+            }
+            Error::CacheNotInitialized => write!(f, "The cache has not initialized yet"),
+            Error::VcsNotFound {
+                command,
+                vcs: Some(vcs),
+                ..
+            } => write!(
+                f,
+                "{} not found in PATH; install it or set `vcs.{}.command`",
+                command,
+                vcs.name()
+            ),
+            Error::VcsNotFound { command, .. } => {
+                write!(f, "command '{}' is not found in PATH", command)
+            }
+            Error::VcsTooOld {
+                vcs,
+                version,
+                required,
+            } => write!(
+                f,
+                "{} {} is too old; version {} or later is required",
+                vcs.name(),
+                version,
+                required
+            ),
+            Error::VcsFailed { command, code } => write!(
+                f,
+                "command '{}' is exited with return code {:?}.",
+                command, code
+            ),
+            Error::RemoteNotFound { url } => write!(f, "remote repository {} is not found", url),
+            Error::VcsUndetermined { url, candidates } if candidates.is_empty() => write!(
+                f,
+                "cannot determine the VCS of {}; specify it with --vcs",
+                url
+            ),
+            Error::VcsUndetermined { url, candidates } => write!(
+                f,
+                "the VCS of {} is ambiguous (candidates: {}); specify it with --vcs",
+                url,
+                candidates
+                    .iter()
+                    .map(|vcs| vcs.name())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            Error::VcsNotRegistered { vcs } => {
+                write!(f, "no backend is registered for VCS '{}'", vcs)
+            }
+            Error::Unsupported { vcs, operation } => {
+                write!(f, "{} is not supported by {} yet", operation, vcs)
+            }
+            Error::DestinationExists { path } => write!(f, "{} already exists", path.display()),
+            Error::NotManaged { path } => write!(f, "{} is not managed by rhq", path.display()),
+            Error::NoMatch { keywords } => {
+                write!(f, "no managed repository matches '{}'", keywords)
+            }
+            Error::LocalChanges { path, reason } => {
+                write!(f, "{} has local changes: {}", path.display(), reason)
+            }
+            Error::InvalidQuery { query, reason } => {
+                write!(f, "invalid query '{}': {}", query, reason)
+            }
+            Error::InvalidPath { path, reason } => write!(f, "invalid path '{}': {}", path, reason),
+            #[cfg(feature = "git2")]
+            Error::Git { source } => write!(f, "{}", source.message()),
+            Error::InvalidUrl { source } => write!(f, "invalid URL: {}", source),
+            Error::Watch { source } => write!(f, "failed to watch the file system: {}", source),
+            Error::Io { source } => write!(f, "{}", source),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::InvalidConfig { source, .. } => Some(source),
+            Error::CacheCorrupt { source, .. } => Some(source),
+            // The message of `VcsNotFound` already tells that the executable is missing.
+            #[cfg(feature = "git2")]
+            Error::Git { source } => Some(source),
+            Error::InvalidUrl { source } => Some(source),
+            Error::Watch { source } => Some(source),
+            Error::Io { source } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(source: io::Error) -> Self {
+        Error::Io { source }
+    }
+}
+
+impl From<url::ParseError> for Error {
+    fn from(source: url::ParseError) -> Self {
+        Error::InvalidUrl { source }
+    }
+}
+
+#[cfg(feature = "git2")]
+impl From<git2::Error> for Error {
+    fn from(source: git2::Error) -> Self {
+        Error::Git { source }
+    }
+}
+
+impl From<notify::Error> for Error {
+    fn from(source: notify::Error) -> Self {
+        Error::Watch { source }
+    }
+}
+
+impl From<walkdir::Error> for Error {
+    fn from(err: walkdir::Error) -> Self {
+        Error::Io { source: err.into() }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{query::Query, util::process};
+
+    #[test]
+    fn missing_command() {
+        let err = process::status(&mut process::piped("rhq-missing-command")).unwrap_err();
+        match err {
+            Error::VcsNotFound {
+                ref command,
+                vcs: None,
+                ..
+            } => assert_eq!(command, "rhq-missing-command"),
+            err => panic!("unexpected error: {:?}", err),
+        }
+        assert_eq!(err.kind(), "vcs-not-found");
+    }
+
+    #[test]
+    fn missing_vcs_is_actionable() {
+        let err = Error::spawn(
+            "/opt/pijul/bin/pijul",
+            io::Error::from(io::ErrorKind::NotFound),
+        );
+        assert_eq!(
+            err.to_string(),
+            "/opt/pijul/bin/pijul not found in PATH; install it or set `vcs.pijul.command`"
+        );
+    }
+
+    #[test]
+    fn invalid_query() {
+        let err = "../relative/path".parse::<Query>().unwrap_err();
+        assert_eq!(err.kind(), "invalid-query");
+    }
+}
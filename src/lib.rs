@@ -7,18 +7,23 @@
 
 mod cache;
 mod config;
-mod printer;
+mod error;
 mod remote;
 mod repository;
+mod scan;
 mod scp;
+mod watch;
 mod workspace;
 
+pub mod printer;
 pub mod query;
 pub mod util;
 pub mod vcs;
 
+pub use self::error::{Error, Result};
 pub use self::query::Query;
 pub use self::remote::Remote;
-pub use self::repository::Repository;
+pub use self::repository::{Link, Metadata, Repository};
+pub use self::scan::ScanOptions;
 pub use self::vcs::Vcs;
 pub use self::workspace::Workspace;
@@ -1,16 +1,419 @@
-use std::fmt::Arguments;
-use std::io::{self, Write};
+//! Defines output messages of workspace operations.
 
-#[derive(Default)]
+use crate::{repository::Repository, vcs::Vcs};
+use chrono::{DateTime, Local};
+use serde::Serialize;
+use std::{
+    env, fmt,
+    io::{self, IsTerminal, Write},
+    path::Path,
+    str::FromStr,
+};
+
+pub const POSSIBLE_MESSAGE_FORMATS: &[&str] = &["human", "json"];
+pub const POSSIBLE_COLOR_CHOICES: &[&str] = &["auto", "always", "never"];
+
+/// The severity of messages.
+///
+/// A message is displayed if its level is less than or equal to the level of `Printer`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Level {
+    Error,
+    Warn,
+    Info,
+    Debug,
+}
+
+/// Whether to use colored output in human readable format.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum ColorChoice {
+    /// Use colors only if the output stream is a terminal.
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+impl FromStr for ColorChoice {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(ColorChoice::Auto),
+            "always" => Ok(ColorChoice::Always),
+            "never" => Ok(ColorChoice::Never),
+            s => Err(format!("{} is invalid color choice", s)),
+        }
+    }
+}
+
+/// The format of messages emitted by `Printer`.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum MessageFormat {
+    /// Human readable text.
+    #[default]
+    Human,
+    /// Newline-delimited JSON objects.
+    Json,
+}
+
+impl FromStr for MessageFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "human" => Ok(MessageFormat::Human),
+            "json" => Ok(MessageFormat::Json),
+            s => Err(format!("{} is invalid message format", s)),
+        }
+    }
+}
+
+/// The result of a diagnostic check.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CheckStatus {
+    Ok,
+    Warning,
+    Error,
+}
+
+impl fmt::Display for CheckStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CheckStatus::Ok => f.write_str("ok"),
+            CheckStatus::Warning => f.write_str("warning"),
+            CheckStatus::Error => f.write_str("error"),
+        }
+    }
+}
+
+/// An event occurred during operations.
+#[derive(Debug, Serialize)]
+#[serde(tag = "event", rename_all = "kebab-case")]
+pub enum Event<'a> {
+    /// A free-form progress message.
+    Progress { level: Level, message: String },
+    /// Cloning of a remote repository is started.
+    CloneStarted {
+        url: &'a str,
+        path: &'a Path,
+        vcs: Vcs,
+    },
+    /// Creation of a new repository is started.
+    InitStarted { path: &'a Path, vcs: Vcs },
+    /// A new entry is added into the cache.
+    RepositoryAdded { repository: &'a Repository },
+    /// An existing entry in the cache is overwritten.
+    RepositoryUpdated { repository: &'a Repository },
+    /// An entry is dropped from the cache.
+    RepositoryDropped { path: &'a Path },
+    /// A managed repository, as listed by `rhq list`.
+    Repository { repository: &'a Repository },
+    /// Repositories cloned from the same remote.
+    Duplicated {
+        remote: &'a str,
+        repositories: Vec<&'a Repository>,
+    },
+    /// The remote URL of a repository is (or will be) rewritten.
+    RemoteChanged {
+        path: &'a Path,
+        from: &'a str,
+        to: &'a str,
+    },
+    /// A repository is (or will be) moved into another location.
+    RepositoryMoved { from: &'a Path, to: &'a Path },
+    /// A repository which is not used for a long time, as reported by `rhq gc`.
+    Stale {
+        repository: &'a Repository,
+        size: u64,
+        last_active: DateTime<Local>,
+    },
+    /// A tag and the number of repositories with it, as listed by `rhq tag list`.
+    Tag { name: &'a str, repositories: usize },
+    /// The result of a diagnostic check, as reported by `rhq doctor`.
+    Check {
+        subject: &'a str,
+        status: CheckStatus,
+        message: String,
+    },
+    /// A recoverable problem.
+    Warning { message: String },
+    /// An operation is failed.
+    Error { kind: &'a str, message: String },
+}
+
+impl<'a> Event<'a> {
+    /// Returns the level of this event, or `None` if the event is a part of command output
+    /// (e.g. entries of `rhq list`) which is always displayed.
+    pub fn level(&self) -> Option<Level> {
+        match self {
+            Event::Progress { level, .. } => Some(*level),
+            Event::CloneStarted { .. }
+            | Event::InitStarted { .. }
+            | Event::RepositoryAdded { .. }
+            | Event::RepositoryUpdated { .. }
+            | Event::RepositoryDropped { .. } => Some(Level::Info),
+            Event::Warning { .. } => Some(Level::Warn),
+            Event::Error { .. } => Some(Level::Error),
+            Event::Repository { .. }
+            | Event::Duplicated { .. }
+            | Event::RemoteChanged { .. }
+            | Event::RepositoryMoved { .. }
+            | Event::Stale { .. }
+            | Event::Tag { .. }
+            | Event::Check { .. } => None,
+        }
+    }
+}
+
+impl<'a> fmt::Display for Event<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Event::Progress { message, .. } => write!(f, "{}", message),
+            Event::CloneStarted { url, path, vcs } => write!(
+                f,
+                "Clone from {} into {} by using {}",
+                url,
+                path.display(),
+                vcs
+            ),
+            Event::InitStarted { path, vcs } => write!(
+                f,
+                "Creating an empty repository at \"{}\" (VCS: {})",
+                path.display(),
+                vcs
+            ),
+            Event::RepositoryAdded { repository } => {
+                write!(f, "Add new entry: {}", repository.path_string())
+            }
+            Event::RepositoryUpdated { repository } => {
+                write!(f, "Overwrite existed entry: {}", repository.path_string())
+            }
+            Event::RepositoryDropped { path } => write!(f, "Dropped: {}", path.display()),
+            Event::Repository { repository } => write!(f, "{}", repository.path_string()),
+            Event::Duplicated {
+                remote,
+                repositories,
+            } => {
+                write!(f, "{}", remote)?;
+                for repository in repositories {
+                    write!(f, "\n    {}", repository.path_string())?;
+                }
+                Ok(())
+            }
+            Event::RemoteChanged { path, from, to } => {
+                write!(f, "{}: {} -> {}", path.display(), from, to)
+            }
+            Event::RepositoryMoved { from, to } => {
+                write!(f, "{} -> {}", from.display(), to.display())
+            }
+            Event::Stale {
+                repository,
+                size,
+                last_active,
+            } => write!(
+                f,
+                "{:>9}  {}  {}",
+                human_size(*size),
+                last_active.format("%Y-%m-%d"),
+                repository.path_string()
+            ),
+            Event::Tag { name, repositories } => write!(f, "{} ({})", name, repositories),
+            Event::Check {
+                subject,
+                status,
+                message,
+            } => write!(f, "[{}] {}: {}", status, subject, message),
+            Event::Warning { message } => write!(f, "{}", message),
+            Event::Error { message, .. } => write!(f, "{}", message),
+        }
+    }
+}
+
+#[derive(Debug)]
 pub struct Printer {
-    pub(crate) verbose: bool,
+    level: Level,
+    format: MessageFormat,
+    color: ColorChoice,
+}
+
+impl Default for Printer {
+    fn default() -> Self {
+        Printer::new(Level::Warn, MessageFormat::Human, ColorChoice::Auto)
+    }
 }
 
 impl Printer {
-    pub fn print(&self, args: Arguments) {
-        if self.verbose {
-            let stdout = io::stdout();
-            let _ = stdout.lock().write_fmt(args);
+    pub fn new(level: Level, format: MessageFormat, color: ColorChoice) -> Self {
+        Printer {
+            level,
+            format,
+            color,
+        }
+    }
+
+    pub fn level(&self) -> Level {
+        self.level
+    }
+
+    pub(crate) fn set_level(&mut self, level: Level) {
+        self.level = level;
+    }
+
+    pub fn format(&self) -> MessageFormat {
+        self.format
+    }
+
+    /// Print a progress message at the level `Info`.
+    pub fn print(&self, args: fmt::Arguments) {
+        self.progress(Level::Info, args)
+    }
+
+    /// Print a progress message at the level `Debug`.
+    pub fn debug(&self, args: fmt::Arguments) {
+        self.progress(Level::Debug, args)
+    }
+
+    fn progress(&self, level: Level, args: fmt::Arguments) {
+        if self.format == MessageFormat::Human && level > self.level {
+            return;
+        }
+        self.emit(Event::Progress {
+            level,
+            message: args.to_string().trim_end().to_owned(),
+        })
+    }
+
+    /// Emit an event.
+    ///
+    /// In JSON format, all events are emitted to stdout regardless of their levels.
+    /// In human readable format, errors and warnings are written to stderr.
+    pub fn emit(&self, event: Event) {
+        if self.format == MessageFormat::Json {
+            if let Ok(line) = serde_json::to_string(&event) {
+                let _ = writeln!(io::stdout().lock(), "{}", line);
+            }
+            return;
+        }
+
+        match event.level() {
+            None => {
+                let _ = writeln!(io::stdout().lock(), "{}", event);
+            }
+            Some(level) if level > self.level => {}
+            Some(level @ Level::Error) | Some(level @ Level::Warn) => {
+                let stderr = io::stderr();
+                let colored = self.use_color(stderr.is_terminal());
+                let _ = writeln!(stderr.lock(), "{}{}", Label { level, colored }, event);
+            }
+            Some(..) => {
+                let _ = writeln!(io::stdout().lock(), "{}", event);
+            }
         }
     }
+
+    fn use_color(&self, is_terminal: bool) -> bool {
+        match self.color {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            ColorChoice::Auto => {
+                is_terminal
+                    && env::var_os("NO_COLOR").is_none()
+                    && env::var("TERM").map(|t| t != "dumb").unwrap_or(true)
+            }
+        }
+    }
+}
+
+/// Format the size in bytes with a binary prefix, e.g. `1.5 MiB`.
+fn human_size(size: u64) -> String {
+    const UNITS: &[&str] = &["KiB", "MiB", "GiB", "TiB"];
+    if size < 1024 {
+        return format!("{} B", size);
+    }
+    let mut size = size as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit + 1 < UNITS.len() {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+/// The prefix of error and warning messages.
+struct Label {
+    level: Level,
+    colored: bool,
+}
+
+impl fmt::Display for Label {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (label, color) = match self.level {
+            Level::Error => ("error", "31"),
+            Level::Warn => ("warning", "33"),
+            Level::Info => ("info", "32"),
+            Level::Debug => ("debug", "34"),
+        };
+        if self.colored {
+            write!(f, "\x1b[1;{}m{}:\x1b[0m ", color, label)
+        } else {
+            write!(f, "{}: ", label)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn event_json() {
+        let event = Event::CloneStarted {
+            url: "https://github.com/ubnt-intrepid/rhq.git",
+            path: Path::new("/tmp/rhq"),
+            vcs: Vcs::Git,
+        };
+        assert_eq!(
+            serde_json::to_string(&event).unwrap(),
+            r#"{"event":"clone-started","url":"https://github.com/ubnt-intrepid/rhq.git","path":"/tmp/rhq","vcs":"Git"}"#
+        );
+        // The label of level is not a part of the message.
+        assert!(event.to_string().starts_with("Clone from "));
+    }
+
+    #[test]
+    fn event_levels() {
+        let warning = Event::Warning {
+            message: "oops".into(),
+        };
+        let dropped = Event::RepositoryDropped {
+            path: Path::new("/tmp/rhq"),
+        };
+        assert_eq!(warning.level(), Some(Level::Warn));
+        assert_eq!(dropped.level(), Some(Level::Info));
+        assert!(Level::Warn < Level::Info);
+    }
+
+    #[test]
+    fn test_human_size() {
+        assert_eq!(human_size(512), "512 B");
+        assert_eq!(human_size(1536), "1.5 KiB");
+        assert_eq!(human_size(3 * 1024 * 1024 * 1024), "3.0 GiB");
+    }
+
+    #[test]
+    fn label() {
+        let label = Label {
+            level: Level::Error,
+            colored: false,
+        };
+        assert_eq!(label.to_string(), "error: ");
+        let label = Label {
+            level: Level::Warn,
+            colored: true,
+        };
+        assert_eq!(label.to_string(), "\x1b[1;33mwarning:\x1b[0m ");
+    }
 }
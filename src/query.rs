@@ -1,5 +1,5 @@
+use crate::error::{Error, Result};
 use crate::scp::ScpPath;
-use anyhow::{anyhow, Result};
 use std::str::FromStr;
 use url::Url;
 
@@ -9,6 +9,8 @@ use url::Url;
 ///
 /// * `<scheme>://[<username>[:<password>]@]<host>/<path-to-repo>.git`
 ///   - Available schemes are: `http[s]`, `ssh` and `git`.
+/// * `file:///<path-to-repo>`
+///   - The host is regarded as `localhost`.
 /// * `<username>@<host>:<path-to-repo>`
 ///   - Equivalent to `ssh://<username>@<host>/<path-to-repo>.git`
 /// * `<path-to-repo>`
@@ -23,6 +25,7 @@ impl Query {
     /// Returns the host name if available.
     pub fn host(&self) -> Option<&str> {
         match *self {
+            Query::Url(ref url) if url.scheme() == "file" => Some("localhost"),
             Query::Url(ref url) => url.host_str(),
             Query::Scp(ref scp) => Some(scp.host()),
             Query::Path(_) => None,
@@ -39,13 +42,18 @@ impl Query {
 }
 
 impl FromStr for Query {
-    type Err = anyhow::Error;
+    type Err = Error;
 
     fn from_str(s: &str) -> Result<Query> {
         if let Ok(url) = Url::parse(s) {
             match url.scheme() {
-                "http" | "https" | "ssh" | "git" => {}
-                scheme => return Err(anyhow!("'{}' is invalid scheme", scheme)),
+                "http" | "https" | "ssh" | "git" | "file" => {}
+                scheme => {
+                    return Err(Error::invalid_query(
+                        s,
+                        format!("'{}' is invalid scheme", scheme),
+                    ))
+                }
             }
             Ok(Query::Url(url))
         } else if let Ok(scp) = ScpPath::from_str(s) {
@@ -56,7 +64,10 @@ impl FromStr for Query {
                 || s.starts_with(".\\")
                 || s.starts_with("..\\")
             {
-                return Err(anyhow!("The path must be not a relative path."));
+                return Err(Error::invalid_query(
+                    s,
+                    "The path must be not a relative path.",
+                ));
             }
             Ok(Query::Path(s.to_owned()))
         }
@@ -98,6 +109,14 @@ mod tests_query {
         }
     }
 
+    #[test]
+    fn file_url() {
+        let s = "file:///srv/repos/foo.git";
+        let query: Query = s.parse().unwrap();
+        assert_eq!(query.host(), Some("localhost"));
+        assert_eq!(query.path(), "srv/repos/foo");
+    }
+
     #[test]
     fn scp_pattern() {
         let ss = &["git@github.com:peco/peco.git", "git@github.com:peco/peco"];
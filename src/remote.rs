@@ -1,10 +1,10 @@
+use crate::error::{Error, Result};
 use crate::{query::Query, scp::ScpPath};
-use anyhow::{anyhow, Result};
 use serde::{Deserialize, Serialize};
 use url::Url;
 
 /// Information of remote repository
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Remote {
     url: String,
 }
@@ -18,13 +18,34 @@ impl Remote {
     pub fn url(&self) -> &str {
         &self.url
     }
+
+    /// Returns a normalized form of the remote URL, as `<host>/<path>`.
+    ///
+    /// The scheme, user name and `.git` suffix are dropped so that
+    /// the HTTPS and SSH URLs of the same project are regarded as identical.
+    pub fn normalized(&self) -> String {
+        match self.url.parse::<Query>() {
+            Ok(ref query) if query.host().is_some() => format!(
+                "{}/{}",
+                query.host().unwrap().to_lowercase(),
+                query.path().trim_end_matches('/')
+            ),
+            _ => self
+                .url
+                .trim_end_matches('/')
+                .trim_end_matches(".git")
+                .to_owned(),
+        }
+    }
 }
 
 impl Remote {
     pub fn from_url(url: &Url) -> Result<Self> {
         let url = if url.scheme() == "ssh" {
             let username = url.username();
-            let host = url.host_str().ok_or_else(|| anyhow!("empty host"))?;
+            let host = url
+                .host_str()
+                .ok_or_else(|| Error::invalid_query(url.as_str(), "empty host"))?;
             let path = url.path().trim_start_matches('/');
             format!("{}@{}:{}", username, host, path)
         } else {
@@ -56,6 +77,41 @@ impl Remote {
             Query::Path(ref path) => Self::from_path(path, is_ssh, host),
         }
     }
+
+    /// Returns `true` if the remote is accessed via SSH.
+    pub fn is_ssh(&self) -> bool {
+        match self.url.parse::<Query>() {
+            Ok(Query::Url(ref url)) => url.scheme() == "ssh",
+            Ok(Query::Scp(..)) => true,
+            _ => false,
+        }
+    }
+
+    /// Make a new remote which points the same project with different protocol and/or host.
+    ///
+    /// If `is_ssh` is `None`, the current protocol is kept.
+    /// If `host` is `None`, the current host name is kept.
+    pub fn rewrite(&self, is_ssh: Option<bool>, host: Option<&str>) -> Result<Self> {
+        let query: Query = self.url.parse()?;
+        let current_host = query
+            .host()
+            .ok_or_else(|| Error::invalid_query(&*self.url, "cannot determine the host"))?;
+        let host = host.unwrap_or(current_host);
+        let is_ssh = is_ssh.unwrap_or_else(|| self.is_ssh());
+
+        if is_ssh == self.is_ssh() {
+            match query {
+                Query::Url(ref url) => {
+                    let mut url = url.clone();
+                    url.set_host(Some(host))?;
+                    return Self::from_url(&url);
+                }
+                Query::Scp(ref scp) => return Ok(Self::from_scp(&scp.with_host(host))),
+                Query::Path(..) => {}
+            }
+        }
+        Self::from_path(query.path(), is_ssh, host)
+    }
 }
 
 #[cfg(test)]
@@ -77,4 +133,39 @@ mod tests {
         let remote = Remote::from_query(&query, true, "github.com").unwrap();
         assert_eq!(remote.url, "git@github.com:ubnt-intrepid/rhq.git");
     }
+
+    #[test]
+    fn normalized_ignores_protocol() {
+        let https = Remote::new("https://github.com/ubnt-intrepid/rhq.git");
+        let scp = Remote::new("git@github.com:ubnt-intrepid/rhq.git");
+        let ssh = Remote::new("ssh://git@GitHub.com/ubnt-intrepid/rhq");
+        assert_eq!(https.normalized(), "github.com/ubnt-intrepid/rhq");
+        assert_eq!(scp.normalized(), "github.com/ubnt-intrepid/rhq");
+        assert_eq!(ssh.normalized(), "github.com/ubnt-intrepid/rhq");
+    }
+
+    #[test]
+    fn rewrite_https_to_ssh() {
+        let remote = Remote::new("https://github.com/ubnt-intrepid/rhq.git");
+        let remote = remote.rewrite(Some(true), None).unwrap();
+        assert_eq!(remote.url, "git@github.com:ubnt-intrepid/rhq.git");
+    }
+
+    #[test]
+    fn rewrite_ssh_to_https() {
+        let remote = Remote::new("git@github.com:ubnt-intrepid/rhq.git");
+        let remote = remote.rewrite(Some(false), None).unwrap();
+        assert_eq!(remote.url, "https://github.com/ubnt-intrepid/rhq.git");
+    }
+
+    #[test]
+    fn rewrite_host() {
+        let remote = Remote::new("https://git.old.corp/team/service.git");
+        let remote = remote.rewrite(None, Some("git.new.corp")).unwrap();
+        assert_eq!(remote.url, "https://git.new.corp/team/service.git");
+
+        let remote = Remote::new("gitlab@git.old.corp:team/service.git");
+        let remote = remote.rewrite(None, Some("git.new.corp")).unwrap();
+        assert_eq!(remote.url, "gitlab@git.new.corp:team/service.git");
+    }
 }
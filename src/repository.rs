@@ -1,20 +1,101 @@
 //! defines functions/types related to local repository access.
 
 use crate::{
+    error::{Error, Result},
     remote::Remote,
     util::{self, process},
-    vcs::Vcs,
+    vcs::{git, Vcs, VcsConfig},
 };
-use anyhow::{anyhow, Result};
+use chrono::{DateTime, Local};
 use serde::{Deserialize, Serialize};
 use std::{
+    collections::BTreeSet,
     ffi::OsStr,
     fmt::Display,
     path::{Path, PathBuf},
 };
 
+/// relation of a working tree to another repository
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+pub enum Link {
+    /// a linked worktree of Git, created by `git worktree add`
+    Worktree {
+        /// path of the main working tree (or the bare repository)
+        main: PathBuf,
+    },
+    /// a submodule of Git
+    Submodule {
+        /// path of the superproject
+        superproject: PathBuf,
+    },
+}
+
+/// additional information of repository
+///
+/// All fields are optional, so that the cache written by older versions can be loaded.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Metadata {
+    /// time when the repository was added under management
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub added_at: Option<DateTime<Local>>,
+    /// time when the repository was accessed through rhq last
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_accessed: Option<DateTime<Local>>,
+    /// number of accesses through rhq
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub access_count: u64,
+    /// time of the latest commit
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_commit_at: Option<DateTime<Local>>,
+    /// default branch of the remote repository
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default_branch: Option<String>,
+    /// total size of files in bytes, measured only when it is needed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
+    /// tags attached by the user
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub tags: BTreeSet<String>,
+}
+
+fn is_zero(n: &u64) -> bool {
+    *n == 0
+}
+
+impl Metadata {
+    /// Record an access to the repository at the current time.
+    pub fn record_access(&mut self) {
+        self.last_accessed = Some(Local::now());
+        self.access_count += 1;
+    }
+
+    /// Returns the score to rank repositories by how often and how recently they are accessed.
+    ///
+    /// The number of accesses is weighted by the time elapsed since the last access at `now`,
+    /// in the same manner as z/zoxide.
+    pub fn frecency(&self, now: DateTime<Local>) -> f64 {
+        let last_accessed = match self.last_accessed {
+            Some(t) => t,
+            None => return 0.0,
+        };
+        let elapsed = now - last_accessed;
+        let weight = if elapsed < chrono::Duration::hours(1) {
+            4.0
+        } else if elapsed < chrono::Duration::days(1) {
+            2.0
+        } else if elapsed < chrono::Duration::weeks(1) {
+            0.5
+        } else {
+            0.25
+        };
+        // entries written by older versions have the time of access but no count.
+        self.access_count.max(1) as f64 * weight
+    }
+}
+
 /// local repository
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Repository {
     /// name of repository
     name: String,
@@ -25,6 +106,12 @@ pub struct Repository {
     /// information of remote repository
     #[serde(skip_serializing_if = "Option::is_none")]
     remote: Option<Remote>,
+    /// relation to another repository
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    link: Option<Link>,
+    /// additional information
+    #[serde(flatten)]
+    metadata: Metadata,
 }
 
 impl Repository {
@@ -38,23 +125,64 @@ impl Repository {
         let name = path
             .file_name()
             .map(|s| s.to_string_lossy().into_owned())
-            .ok_or_else(|| anyhow!("cannot determine repository name"))?;
+            .ok_or_else(|| Error::InvalidPath {
+                path: path.display().to_string(),
+                reason: "cannot determine repository name".to_owned(),
+            })?;
+        let link = match vcs {
+            Vcs::Git => git::link(&path),
+            _ => None,
+        };
         Ok(Repository {
             name,
             path,
             vcs,
             remote: remote.into(),
+            link,
+            metadata: Metadata::default(),
         })
     }
 
-    /// Check existence of repository and drop if not exists.
-    pub fn refresh(self) -> Option<Self> {
-        match self.vcs.get_remote_url(&self.path) {
-            Ok(url) => Self::new(self.path, self.vcs, url.map(Remote::new)).ok(),
-            _ => None,
+    /// Update the information obtained from the VCS: the last commit and the default branch.
+    ///
+    /// The fields which the VCS cannot tell are left as they are.
+    /// This spawns several VCS processes, so it is called only when the information is needed.
+    /// The size is not measured here, since it requires walking all the files; see `measure_size`.
+    pub fn collect_metadata(&mut self, config: &VcsConfig) {
+        if let Ok(time) = self.vcs.last_commit_time(config, &self.path) {
+            self.metadata.last_commit_at = time;
+        }
+        if let Ok(branch) = self.vcs.default_branch(config, &self.path) {
+            self.metadata.default_branch = branch;
         }
     }
 
+    /// Measure the total size of files in the working directory, and record it.
+    pub fn measure_size(&mut self) -> Result<u64> {
+        let size = util::dir_size(&self.path)?;
+        self.metadata.size = Some(size);
+        Ok(size)
+    }
+
+    /// Check existence of repository and drop if not exists.
+    ///
+    /// The remote is kept as it is if the VCS cannot tell it, or its executable is not found.
+    pub fn refresh(self, config: &VcsConfig) -> Option<Self> {
+        let remote = match self.vcs.get_remote_url(config, &self.path) {
+            Ok(url) => url.map(Remote::new),
+            Err(Error::Unsupported { .. }) | Err(Error::VcsNotFound { .. }) => self.remote,
+            _ => return None,
+        };
+        let mut repo = Self::new(self.path, self.vcs, remote).ok()?;
+        repo.metadata = self.metadata;
+        Some(repo)
+    }
+
+    /// Change the VCS of this entry, e.g. when a Git repository is colocated with Jujutsu.
+    pub fn set_vcs(&mut self, vcs: Vcs) {
+        self.vcs = vcs;
+    }
+
     pub fn is_same_local(&self, other: &Self) -> bool {
         self.path.as_path() == other.path.as_path()
     }
@@ -69,22 +197,122 @@ impl Repository {
         I: IntoIterator<Item = S>,
         S: AsRef<OsStr> + Display,
     {
-        let output = process::inherit(command)
-            .args(args)
-            .current_dir(&self.path)
-            .output()?;
+        let output = process::output(process::inherit(command).args(args).current_dir(&self.path))?;
         Ok(output.status.success())
     }
 
+    /// Change the URL of remote repository, both in the VCS configuration and this entry.
+    pub fn set_remote(&mut self, config: &VcsConfig, remote: Remote) -> Result<()> {
+        self.vcs.set_remote_url(config, &self.path, remote.url())?;
+        self.remote = Some(remote);
+        Ok(())
+    }
+
+    /// Move the working directory to `dest` and update the location of this entry.
+    pub fn move_to<P: AsRef<Path>>(&mut self, dest: P) -> Result<()> {
+        util::move_dir(&self.path, &dest)?;
+        let metadata = self.metadata.clone();
+        *self = Self::new(dest, self.vcs, self.remote.clone())?;
+        self.metadata = metadata;
+        Ok(())
+    }
+
     pub fn name(&self) -> &str {
         &self.name
     }
 
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
     pub fn path_string(&self) -> String {
         format!("{}", self.path.display())
     }
 
+    pub fn vcs(&self) -> Vcs {
+        self.vcs
+    }
+
     pub fn remote(&self) -> Option<&Remote> {
         self.remote.as_ref()
     }
+
+    pub fn metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+
+    pub fn metadata_mut(&mut self) -> &mut Metadata {
+        &mut self.metadata
+    }
+
+    /// Returns the relation to another repository, if this is a linked worktree or a submodule.
+    pub fn link(&self) -> Option<&Link> {
+        self.link.as_ref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn load_entry_without_metadata() {
+        let repo: Repository = serde_json::from_str(
+            r#"{"name":"rhq","path":"/home/user/rhq","vcs":"Git","remote":{"url":"https://github.com/ubnt-intrepid/rhq.git"}}"#,
+        )
+        .unwrap();
+        assert_eq!(repo.metadata(), &Metadata::default());
+        assert_eq!(repo.link(), None);
+
+        let json = serde_json::to_string(&repo).unwrap();
+        assert!(!json.contains("added_at"));
+        assert!(!json.contains("tags"));
+    }
+
+    #[test]
+    fn frecency_prefers_frequent_and_recent() {
+        let now = Local::now();
+        let metadata = |count, hours| Metadata {
+            last_accessed: Some(now - chrono::Duration::hours(hours)),
+            access_count: count,
+            ..Metadata::default()
+        };
+        assert_eq!(Metadata::default().frecency(now), 0.0);
+        assert!(metadata(1, 0).frecency(now) > metadata(1, 48).frecency(now));
+        assert!(metadata(10, 48).frecency(now) > metadata(1, 0).frecency(now));
+        assert!(metadata(0, 0).frecency(now) > 0.0);
+
+        let mut metadata = Metadata::default();
+        metadata.record_access();
+        metadata.record_access();
+        assert_eq!(metadata.access_count, 2);
+        assert!(metadata.last_accessed.is_some());
+    }
+
+    #[test]
+    fn move_keeps_metadata() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("a");
+        std::fs::create_dir_all(path.join(".hg")).unwrap();
+        let mut repo = Repository::new(&path, Vcs::Hg, None).unwrap();
+        repo.metadata_mut().added_at = Some(Local::now());
+        let metadata = repo.metadata().clone();
+
+        repo.move_to(dir.path().join("b")).unwrap();
+        assert_eq!(repo.name(), "b");
+        assert_eq!(repo.metadata(), &metadata);
+    }
+
+    #[test]
+    fn size_is_measured_only_on_demand() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join(".hg")).unwrap();
+        std::fs::write(dir.path().join("README"), "hello").unwrap();
+        let mut repo = Repository::new(dir.path(), Vcs::Hg, None).unwrap();
+
+        repo.collect_metadata(&VcsConfig::new(Vcs::Hg));
+        assert_eq!(repo.metadata().size, None);
+        assert_eq!(repo.measure_size().unwrap(), 5);
+        assert_eq!(repo.metadata().size, Some(5));
+    }
 }
@@ -0,0 +1,594 @@
+//! Scanning directories to find repositories.
+
+use crate::{
+    config::ConfigData,
+    error::{Error, Result},
+    remote::Remote,
+    repository::{Link, Repository},
+    vcs::{self, git, Vcs},
+};
+use chrono::{DateTime, Local};
+use glob::Pattern;
+use ignore::{WalkBuilder, WalkState};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashSet},
+    fs,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::UNIX_EPOCH,
+};
+
+/// The name of ignore files specific to rhq, written in the same format as `.gitignore`.
+///
+/// An empty file makes the directory containing it skipped entirely.
+pub const IGNORE_FILE: &str = ".rhqignore";
+
+/// The directories of VCS metadata, which are never traversed.
+const METADATA_DIRS: &[&str] = &[".git", ".hg", ".jj", "_darcs", ".pijul", ".svn"];
+
+/// Options of scanning directories.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScanOptions {
+    /// The maximal depth of traversal.
+    pub depth: Option<usize>,
+    /// Whether to find independent repositories nested in other repositories.
+    #[serde(default)]
+    pub nested: bool,
+    /// Whether to find submodules of Git.
+    #[serde(default)]
+    pub submodules: bool,
+}
+
+impl ScanOptions {
+    /// Returns whether the traversal descends into repositories.
+    fn descends(&self) -> bool {
+        self.nested || self.submodules
+    }
+}
+
+/// A repository found by scanning, with the result of resolving its remote URL.
+pub type Found = (PathBuf, Result<Repository>);
+
+/// The metadata of the last scan under a directory, which is used to skip unchanged subtrees.
+///
+/// The modification time of a directory changes only when its entries are added, removed or renamed,
+/// so every traversed directory is recorded.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ScanState {
+    /// The time when the scan was performed.
+    pub scanned_at: DateTime<Local>,
+    /// The options used in the scan.
+    #[serde(flatten)]
+    options: ScanOptions,
+    /// The exclude patterns used in the scan.
+    excludes: Vec<String>,
+    /// The names of skipped directories used in the scan.
+    #[serde(default)]
+    skip_dirs: Vec<String>,
+    /// The modification times of traversed directories, in nanoseconds since the Unix epoch.
+    directories: BTreeMap<PathBuf, u64>,
+    /// The roots of repositories found in the scan.
+    repositories: BTreeSet<PathBuf>,
+}
+
+impl ScanState {
+    fn new(options: ScanOptions, config: &ConfigData) -> Self {
+        ScanState {
+            scanned_at: Local::now(),
+            options,
+            excludes: excludes(config),
+            skip_dirs: config.skip_dirs.clone(),
+            directories: BTreeMap::new(),
+            repositories: BTreeSet::new(),
+        }
+    }
+
+    /// Returns the roots of repositories found in the scan.
+    pub fn repositories(&self) -> impl Iterator<Item = &Path> {
+        self.repositories.iter().map(PathBuf::as_path)
+    }
+
+    fn merge(&mut self, walk: Walk) -> Vec<Found> {
+        self.directories.extend(walk.directories);
+        self.repositories
+            .extend(walk.found.iter().map(|(path, _)| path.clone()));
+        walk.found
+    }
+}
+
+fn excludes(config: &ConfigData) -> Vec<String> {
+    config
+        .exclude_patterns
+        .iter()
+        .map(|ex| ex.as_str().to_owned())
+        .collect()
+}
+
+/// Find all repositories under `root`.
+///
+/// The results are sorted by their paths.
+pub fn scan(root: &Path, options: ScanOptions, config: &ConfigData) -> (Vec<Found>, ScanState) {
+    let mut state = ScanState::new(options, config);
+    let mut found = state.merge(walk(root, options, config, None));
+    found.sort_by(|(a, _), (b, _)| a.cmp(b));
+    (found, state)
+}
+
+/// Find repositories under `root` which are added after the scan recorded in `last`.
+///
+/// Only the directories modified since the last scan are read, and the remote URLs of
+/// repositories found in the last scan are not resolved again.
+/// All directories are scanned if the options, exclude patterns or skipped directories have changed.
+pub fn rescan(
+    root: &Path,
+    options: ScanOptions,
+    config: &ConfigData,
+    last: &ScanState,
+) -> (Vec<Found>, ScanState) {
+    if last.options != options
+        || last.excludes != excludes(config)
+        || last.skip_dirs != config.skip_dirs
+    {
+        return scan(root, options, config);
+    }
+
+    let mut state = ScanState::new(options, config);
+    let mut changed = vec![];
+    for (dir, &mtime) in &last.directories {
+        // Removed directories are dropped, and the change is detected at their parents.
+        if let Some(current) = modified(dir) {
+            state.directories.insert(dir.clone(), current);
+            if current != mtime {
+                changed.push(dir.clone());
+            }
+        }
+    }
+    for path in &last.repositories {
+        if vcs::detect_from_path(path).is_some() {
+            state.repositories.insert(path.clone());
+        } else if path.is_dir() {
+            // It may have become an ordinary directory which contains repositories.
+            changed.push(path.clone());
+        }
+    }
+    if !state.directories.contains_key(root) && !state.repositories.contains(root) {
+        return scan(root, options, config);
+    }
+    if changed.is_empty() {
+        return (vec![], state);
+    }
+
+    let scope = Scope {
+        ancestors: changed
+            .iter()
+            .flat_map(|dir| dir.ancestors().take_while(|path| path.starts_with(root)))
+            .map(Path::to_owned)
+            .collect(),
+        known: state
+            .directories
+            .keys()
+            .chain(&state.repositories)
+            .cloned()
+            .collect(),
+    };
+    let mut found = state.merge(walk(root, options, config, Some(scope)));
+    // Repositories containing changed directories are traversed again when nested ones are searched.
+    found.retain(|(path, _)| !last.repositories.contains(path));
+    found.sort_by(|(a, _), (b, _)| a.cmp(b));
+    (found, state)
+}
+
+/// The directories to be traversed in a rescan.
+///
+/// The traversal always starts at the root, so that ignore files are applied in the same way as
+/// the full scan, but it only goes through the changed directories and unknown ones.
+struct Scope {
+    /// The changed directories and their ancestors.
+    ancestors: HashSet<PathBuf>,
+    /// The directories and repositories found in the last scan.
+    known: HashSet<PathBuf>,
+}
+
+impl Scope {
+    fn contains(&self, path: &Path) -> bool {
+        self.ancestors.contains(path) || !self.known.contains(path)
+    }
+}
+
+/// The result of traversal under a directory.
+struct Walk {
+    found: Vec<Found>,
+    directories: Vec<(PathBuf, u64)>,
+}
+
+/// Configure the traversal under `root`.
+///
+/// The traversal skips directories which are ignored by ignore files, excluded by the configuration,
+/// listed in `skip_dirs`, or out of `scope`, as well as the metadata of VCSs.
+/// Ignore files in the ancestors of `root` are not used.
+/// Symbolic links are followed, but loops and other file systems (e.g. network mounts) are skipped.
+fn builder(
+    root: &Path,
+    options: ScanOptions,
+    config: &ConfigData,
+    scope: Option<Scope>,
+) -> WalkBuilder {
+    let excludes = config.exclude_patterns.clone();
+    let skip_dirs = config.skip_dirs.clone();
+    let mut builder = WalkBuilder::new(root);
+    builder
+        .standard_filters(false)
+        .git_ignore(true)
+        .ignore(true)
+        .require_git(false)
+        .add_custom_ignore_filename(IGNORE_FILE)
+        .follow_links(true)
+        .same_file_system(true)
+        .max_depth(options.depth)
+        .filter_entry(move |entry| {
+            if entry.depth() == 0 || !entry.file_type().is_some_and(|ty| ty.is_dir()) {
+                return true;
+            }
+            let path = entry.path();
+            let skipped = entry.file_name().to_str().is_some_and(|name| {
+                METADATA_DIRS.contains(&name) || skip_dirs.iter().any(|dir| dir == name)
+            });
+            !skipped
+                && scope.as_ref().is_none_or(|scope| scope.contains(path))
+                && !is_excluded(path, &excludes)
+                && !has_ignore_marker(path)
+        });
+    builder
+}
+
+fn has_ignore_marker(path: &Path) -> bool {
+    fs::metadata(path.join(IGNORE_FILE)).is_ok_and(|m| m.is_file() && m.len() == 0)
+}
+
+/// Find repositories under `root` in parallel, and resolve their remote URLs concurrently.
+///
+/// The traversal does not descend into the repositories found, unless nested repositories or submodules
+/// are searched.
+fn walk(root: &Path, options: ScanOptions, config: &ConfigData, scope: Option<Scope>) -> Walk {
+    let walk = Mutex::new(Walk {
+        found: vec![],
+        directories: vec![],
+    });
+    builder(root, options, config, scope)
+        .build_parallel()
+        .run(|| {
+            Box::new(|entry| {
+                let entry = match entry {
+                    Ok(entry) => entry,
+                    Err(..) => return WalkState::Continue,
+                };
+                if !entry.file_type().is_some_and(|ty| ty.is_dir()) {
+                    return WalkState::Continue;
+                }
+                match vcs::detect_from_path(entry.path()) {
+                    Some(vcs) if !options.descends() => {
+                        let repo = repository_from_path(config, entry.path(), vcs);
+                        walk.lock()
+                            .unwrap_or_else(|e| e.into_inner())
+                            .found
+                            .push((entry.into_path(), repo));
+                        WalkState::Skip
+                    }
+                    Some(vcs) => {
+                        let path = entry.path();
+                        // Nested repositories are detected by the changes of entries in the repository.
+                        let mtime = modified(path);
+                        let repo = if is_wanted(root, path, vcs, options) {
+                            Some(repository_from_path(config, path, vcs))
+                        } else {
+                            None
+                        };
+                        let mut walk = walk.lock().unwrap_or_else(|e| e.into_inner());
+                        if let Some(repo) = repo {
+                            walk.found.push((path.to_owned(), repo));
+                        }
+                        if let Some(mtime) = mtime {
+                            walk.directories.push((path.to_owned(), mtime));
+                        }
+                        // A bare repository has no working directory to contain other repositories.
+                        if vcs == Vcs::Git && git::is_bare(path) {
+                            WalkState::Skip
+                        } else {
+                            WalkState::Continue
+                        }
+                    }
+                    None => {
+                        // The modification time must be read before the entries are listed.
+                        if let Some(mtime) = modified(entry.path()) {
+                            walk.lock()
+                                .unwrap_or_else(|e| e.into_inner())
+                                .directories
+                                .push((entry.into_path(), mtime));
+                        }
+                        WalkState::Continue
+                    }
+                }
+            })
+        });
+    walk.into_inner().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Find the outermost repository containing `path` under `root`, in the same way as `scan`.
+///
+/// Ignore files other than the empty `.rhqignore` are not consulted.
+pub fn enclosing_repository(
+    root: &Path,
+    path: &Path,
+    config: &ConfigData,
+) -> Option<(PathBuf, Vcs)> {
+    let relative = path.strip_prefix(root).ok()?;
+    let mut dir = root.to_owned();
+    for (depth, component) in std::iter::once(None)
+        .chain(relative.components().map(Some))
+        .enumerate()
+    {
+        if let Some(component) = component {
+            dir.push(component);
+        }
+        if depth > 0 {
+            let name = component?.as_os_str().to_str().unwrap_or_default();
+            if METADATA_DIRS.contains(&name)
+                || config.skip_dirs.iter().any(|dir| dir == name)
+                || is_excluded(&dir, &config.exclude_patterns)
+                || has_ignore_marker(&dir)
+            {
+                return None;
+            }
+        }
+        if !dir.is_dir() {
+            return None;
+        }
+        if let Some(vcs) = vcs::detect_from_path(&dir) {
+            return Some((dir, vcs));
+        }
+    }
+    None
+}
+
+/// Check whether the repository at `path` should be found when the traversal descends into repositories.
+fn is_wanted(root: &Path, path: &Path, vcs: Vcs, options: ScanOptions) -> bool {
+    if path == root {
+        return true;
+    }
+    if vcs == Vcs::Git {
+        if let Some(Link::Submodule { .. }) = git::link(path) {
+            return options.submodules;
+        }
+    }
+    let is_nested = path
+        .ancestors()
+        .skip(1)
+        .take_while(|dir| dir.starts_with(root))
+        .any(|dir| vcs::detect_from_path(dir).is_some());
+    !is_nested || options.nested
+}
+
+/// Returns the modification time of the directory at `path`.
+fn modified(path: &Path) -> Option<u64> {
+    let metadata = fs::metadata(path).ok().filter(|m| m.is_dir())?;
+    let elapsed = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+    Some(elapsed.as_nanos() as u64)
+}
+
+fn is_excluded(path: &Path, excludes: &[Pattern]) -> bool {
+    let path = path.to_string_lossy();
+    excludes.iter().any(|ex| ex.matches(&path))
+}
+
+/// Make an entry of the repository at `path`.
+///
+/// The entry has no remote if the repository has no remotes, or the backend cannot tell it.
+pub fn repository_from_path(config: &ConfigData, path: &Path, vcs: Vcs) -> Result<Repository> {
+    let remote = match vcs.get_remote_url(&config.vcs(vcs), path) {
+        Ok(url) => url.map(Remote::new),
+        Err(Error::Unsupported { .. }) => None,
+        Err(e) => return Err(e),
+    };
+    Repository::new(path, vcs, remote)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util;
+    use std::collections::HashMap;
+
+    fn config(root: &Path) -> ConfigData {
+        ConfigData {
+            root_dir: root.to_owned(),
+            host: "github.com".to_owned(),
+            include_dirs: vec![],
+            exclude_patterns: vec![Pattern::new("**/temp").unwrap()],
+            skip_dirs: vec!["node_modules".to_owned()],
+            groups: Default::default(),
+            vcs: HashMap::new(),
+            stdout_to_stderr: false,
+        }
+    }
+
+    fn paths(found: &[Found]) -> Vec<&Path> {
+        found.iter().map(|(path, _)| path.as_path()).collect()
+    }
+
+    #[test]
+    fn scan_prunes_repositories_and_excludes() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = util::canonicalize_pretty(dir.path()).unwrap();
+        for path in &["a/.git", "a/nested/.git", "b/c/.hg", "temp/d/.git", "e"] {
+            fs::create_dir_all(root.join(path)).unwrap();
+        }
+        let config = config(&root);
+
+        let (found, state) = scan(&root, ScanOptions::default(), &config);
+        assert_eq!(paths(&found), [root.join("a"), root.join("b/c")]);
+        assert_eq!(
+            state.repositories().collect::<Vec<_>>(),
+            [root.join("a"), root.join("b/c")]
+        );
+
+        let (found, _) = scan(
+            &root,
+            ScanOptions {
+                depth: Some(1),
+                ..Default::default()
+            },
+            &config,
+        );
+        assert_eq!(paths(&found), [root.join("a")]);
+    }
+
+    #[test]
+    fn rescan_only_changed_directories() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = util::canonicalize_pretty(dir.path()).unwrap();
+        for path in &["a/.git", "b/c/.hg"] {
+            fs::create_dir_all(root.join(path)).unwrap();
+        }
+        let config = config(&root);
+        let (_, state) = scan(&root, ScanOptions::default(), &config);
+
+        let (found, state) = rescan(&root, ScanOptions::default(), &config, &state);
+        assert!(found.is_empty());
+
+        fs::create_dir_all(root.join("b/new/.git")).unwrap();
+        fs::create_dir_all(root.join("d/e/.git")).unwrap();
+        fs::create_dir_all(root.join("temp/f/.git")).unwrap();
+        let (found, state) = rescan(&root, ScanOptions::default(), &config, &state);
+        assert_eq!(paths(&found), [root.join("b/new"), root.join("d/e")]);
+
+        // ignored by the ignore file in an ancestor of the changed directory.
+        fs::write(root.join(".gitignore"), "build/\n").unwrap();
+        fs::create_dir_all(root.join("b/build/g/.git")).unwrap();
+        let (found, state) = rescan(&root, ScanOptions::default(), &config, &state);
+        assert!(found.is_empty());
+
+        fs::remove_dir_all(root.join("d")).unwrap();
+        let (found, state) = rescan(&root, ScanOptions::default(), &config, &state);
+        assert!(found.is_empty());
+        assert_eq!(
+            state.repositories().collect::<Vec<_>>(),
+            [root.join("a"), root.join("b/c"), root.join("b/new")]
+        );
+    }
+
+    #[test]
+    fn scan_skips_ignored_directories() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = util::canonicalize_pretty(dir.path()).unwrap();
+        for path in &[
+            "a/.git",
+            "build/b/.git",
+            "node_modules/c/.git",
+            "private/d/.git",
+            "tools/e/.git",
+            "tools/f/.git",
+        ] {
+            fs::create_dir_all(root.join(path)).unwrap();
+        }
+        fs::write(root.join(".gitignore"), "build/\n").unwrap();
+        fs::write(root.join("private").join(IGNORE_FILE), "").unwrap();
+        fs::write(root.join("tools").join(IGNORE_FILE), "f\n").unwrap();
+        let config = config(&root);
+
+        let (found, _) = scan(&root, ScanOptions::default(), &config);
+        assert_eq!(paths(&found), [root.join("a"), root.join("tools/e")]);
+    }
+
+    #[test]
+    fn scan_nested_repositories_and_submodules() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = util::canonicalize_pretty(dir.path()).unwrap();
+        for path in &["a/.git/modules/sub", "a/sub", "a/vendor/b/.git", "c/.git"] {
+            fs::create_dir_all(root.join(path)).unwrap();
+        }
+        fs::write(root.join("a/sub/.git"), "gitdir: ../.git/modules/sub\n").unwrap();
+        let config = config(&root);
+        let scan_with = |nested, submodules| {
+            let options = ScanOptions {
+                depth: None,
+                nested,
+                submodules,
+            };
+            scan(&root, options, &config)
+        };
+
+        let (found, _) = scan_with(false, false);
+        assert_eq!(paths(&found), [root.join("a"), root.join("c")]);
+        let (found, _) = scan_with(true, false);
+        assert_eq!(
+            paths(&found),
+            [root.join("a"), root.join("a/vendor/b"), root.join("c")]
+        );
+        let (found, _) = scan_with(false, true);
+        assert_eq!(
+            paths(&found),
+            [root.join("a"), root.join("a/sub"), root.join("c")]
+        );
+
+        let (_, state) = scan_with(true, true);
+        fs::create_dir_all(root.join("a/d/.git")).unwrap();
+        let (found, _) = rescan(&root, state.options, &config, &state);
+        assert_eq!(paths(&found), [root.join("a/d")]);
+    }
+
+    #[test]
+    fn find_enclosing_repository() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = util::canonicalize_pretty(dir.path()).unwrap();
+        for path in &[
+            "a/b/.git/refs",
+            "a/b/c/.git",
+            "node_modules/d/.git",
+            "temp/e/.git",
+        ] {
+            fs::create_dir_all(root.join(path)).unwrap();
+        }
+        let config = config(&root);
+
+        let repo = Some((root.join("a/b"), Vcs::Git));
+        assert_eq!(
+            enclosing_repository(&root, &root.join("a/b"), &config),
+            repo
+        );
+        assert_eq!(
+            enclosing_repository(&root, &root.join("a/b/.git/refs/HEAD"), &config),
+            repo
+        );
+        assert_eq!(
+            enclosing_repository(&root, &root.join("a/b/c"), &config),
+            repo
+        );
+        assert_eq!(enclosing_repository(&root, &root.join("a"), &config), None);
+        assert_eq!(
+            enclosing_repository(&root, &root.join("node_modules/d"), &config),
+            None
+        );
+        assert_eq!(
+            enclosing_repository(&root, &root.join("temp/e"), &config),
+            None
+        );
+        assert_eq!(
+            enclosing_repository(&root, &root.join("f/g"), &config),
+            None
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn scan_symlink_loop() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = util::canonicalize_pretty(dir.path()).unwrap();
+        fs::create_dir_all(root.join("a/.git")).unwrap();
+        fs::create_dir_all(root.join("b")).unwrap();
+        std::os::unix::fs::symlink(&root, root.join("b/loop")).unwrap();
+
+        let (found, _) = scan(&root, ScanOptions::default(), &config(&root));
+        assert_eq!(paths(&found), [root.join("a")]);
+    }
+}
@@ -1,4 +1,4 @@
-use anyhow::{anyhow, Result};
+use crate::error::{Error, Result};
 use lazy_static::lazy_static;
 use regex::Regex;
 use std::{fmt, str::FromStr};
@@ -22,10 +22,19 @@ impl ScpPath {
     pub fn path(&self) -> &str {
         &self.path
     }
+
+    /// Returns a copy of this path with the host name replaced.
+    pub fn with_host(&self, host: &str) -> ScpPath {
+        ScpPath {
+            username: self.username.clone(),
+            host: host.to_owned(),
+            path: self.path.clone(),
+        }
+    }
 }
 
 impl FromStr for ScpPath {
-    type Err = anyhow::Error;
+    type Err = Error;
 
     fn from_str(s: &str) -> Result<ScpPath> {
         lazy_static! {
@@ -34,7 +43,7 @@ impl FromStr for ScpPath {
         }
         let cap = RE_SCP
             .captures(s)
-            .ok_or_else(|| anyhow!("does not match"))?;
+            .ok_or_else(|| Error::invalid_query(s, "does not match SCP-like pattern"))?;
 
         let username = cap
             .get(1)
@@ -1,13 +1,18 @@
-use anyhow::Result;
+use crate::error::{Error, Result};
 use shellexpand;
 use std::borrow::Borrow;
 use std::fs;
+use std::io;
 use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
 
 pub fn make_path_buf<S: AsRef<str>>(s: S) -> Result<PathBuf> {
     shellexpand::full(s.as_ref())
         .map(|s| PathBuf::from(s.borrow() as &str))
-        .map_err(Into::into)
+        .map_err(|e| Error::InvalidPath {
+            path: s.as_ref().to_owned(),
+            reason: e.to_string(),
+        })
 }
 
 #[cfg(windows)]
@@ -53,17 +58,108 @@ where
     P: AsRef<Path>,
     F: FnOnce(&mut fs::File) -> Result<()>,
 {
-    fs::create_dir_all(path.as_ref().parent().unwrap())?;
+    // The content is written into a temporary file and renamed,
+    // so that other processes never read the file partially written.
+    let path = path.as_ref();
+    fs::create_dir_all(path.parent().unwrap())?;
+    let mut temp = path.as_os_str().to_owned();
+    temp.push(".tmp");
     let mut file = fs::OpenOptions::new()
         .write(true)
         .create(true)
         .truncate(true)
-        .open(&path)?;
-    write_fn(&mut file)
+        .open(&temp)?;
+    write_fn(&mut file)?;
+    file.sync_all()?;
+    drop(file);
+    fs::rename(&temp, path)?;
+    Ok(())
+}
+
+/// Move a directory to another location, which may be on a different filesystem.
+///
+/// This function refuses to overwrite when the destination already exists.
+pub fn move_dir<P, Q>(src: P, dst: Q) -> Result<()>
+where
+    P: AsRef<Path>,
+    Q: AsRef<Path>,
+{
+    let (src, dst) = (src.as_ref(), dst.as_ref());
+    if dst.exists() {
+        return Err(Error::DestinationExists { path: dst.into() });
+    }
+    if let Some(parent) = dst.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    match fs::rename(src, dst) {
+        Ok(()) => Ok(()),
+        // rename(2) cannot move the entries across filesystems.
+        Err(ref e) if e.kind() == io::ErrorKind::CrossesDevices => copy_and_remove(src, dst),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Copy the directory `src` into `dst`, and then remove `src`.
+///
+/// If copying fails, `dst` is removed so that no half-copied directory is left.
+fn copy_and_remove(src: &Path, dst: &Path) -> Result<()> {
+    if let Err(e) = copy_dir(src, dst) {
+        let _ = fs::remove_dir_all(dst);
+        return Err(e);
+    }
+    fs::remove_dir_all(src).map_err(Into::into)
+}
+
+fn copy_dir(src: &Path, dst: &Path) -> Result<()> {
+    for entry in WalkDir::new(src) {
+        let entry = entry?;
+        let target = match entry.path().strip_prefix(src) {
+            Ok(relative) => dst.join(relative),
+            Err(..) => continue,
+        };
+        let file_type = entry.file_type();
+        if file_type.is_dir() {
+            fs::create_dir_all(&target)?;
+        } else if file_type.is_symlink() {
+            copy_symlink(entry.path(), &target)?;
+        } else {
+            fs::copy(entry.path(), &target)?;
+        }
+    }
+    Ok(())
+}
+
+/// Returns the total size of files under `path` in bytes, without following symbolic links.
+pub fn dir_size<P: AsRef<Path>>(path: P) -> Result<u64> {
+    let mut size = 0;
+    for entry in WalkDir::new(path) {
+        let entry = entry?;
+        if entry.file_type().is_file() {
+            size += entry.metadata()?.len();
+        }
+    }
+    Ok(size)
+}
+
+#[cfg(unix)]
+fn copy_symlink(src: &Path, dst: &Path) -> Result<()> {
+    std::os::unix::fs::symlink(fs::read_link(src)?, dst).map_err(Into::into)
+}
+
+#[cfg(not(unix))]
+fn copy_symlink(src: &Path, dst: &Path) -> Result<()> {
+    fs::copy(src, dst).map(drop).map_err(Into::into)
 }
 
 pub mod process {
-    use std::process::{Command, Stdio};
+    use crate::error::{Error, Result};
+    use std::{
+        env,
+        io::{self, Read, Write},
+        path::{Path, PathBuf},
+        process::{Command, Output, Stdio},
+    };
 
     pub fn inherit(name: &str) -> Command {
         let mut command = Command::new(name);
@@ -80,4 +176,163 @@ pub mod process {
         command.stderr(Stdio::piped());
         command
     }
+
+    /// Run the command and check its exit status.
+    pub fn status(command: &mut Command) -> Result<()> {
+        let name = program_name(command);
+        let st = command.status().map_err(|e| Error::spawn(&name, e))?;
+        match st.code() {
+            Some(0) => Ok(()),
+            code => Err(Error::VcsFailed {
+                command: name,
+                code,
+            }),
+        }
+    }
+
+    /// Run the command and check its exit status, as `status`.
+    ///
+    /// The standard error of the command is forwarded to the standard error of this process,
+    /// and its content is returned together with the exit code.
+    pub fn status_with_stderr(command: &mut Command) -> Result<(Option<i32>, String)> {
+        let name = program_name(command);
+        let mut child = command
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| Error::spawn(&name, e))?;
+
+        let mut captured = vec![];
+        let forwarded = match child.stderr.take() {
+            Some(mut stderr) => forward_stderr(&mut stderr, &mut captured),
+            None => Ok(()),
+        };
+        // Wait for the child even if reading failed, not to leave a zombie.
+        let st = child.wait()?;
+        forwarded?;
+        Ok((st.code(), String::from_utf8_lossy(&captured).into_owned()))
+    }
+
+    /// Copy the bytes read from `reader` into the standard error as they are
+    /// (including the progress lines rewritten by `\r`), and keep them into `captured`.
+    fn forward_stderr<R: Read>(reader: &mut R, captured: &mut Vec<u8>) -> io::Result<()> {
+        let mut buf = [0; 4096];
+        loop {
+            let n = match reader.read(&mut buf) {
+                Ok(0) => return Ok(()),
+                Ok(n) => n,
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            };
+            let mut stderr = io::stderr().lock();
+            let _ = stderr.write_all(&buf[..n]).and_then(|()| stderr.flush());
+            captured.extend_from_slice(&buf[..n]);
+        }
+    }
+
+    /// Run the command and collect its output.
+    ///
+    /// Note that this function does not check the exit status.
+    pub fn output(command: &mut Command) -> Result<Output> {
+        let name = program_name(command);
+        command.output().map_err(|e| Error::spawn(&name, e))
+    }
+
+    /// Find the location of executable from the directories listed in `PATH`.
+    pub fn find_executable(name: &str) -> Option<PathBuf> {
+        if Path::new(name).components().count() > 1 {
+            return Some(PathBuf::from(name)).filter(|path| path.is_file());
+        }
+        let extensions = if cfg!(windows) {
+            env::var("PATHEXT").unwrap_or_else(|_| ".COM;.EXE;.BAT;.CMD".into())
+        } else {
+            String::new()
+        };
+        let extensions: Vec<&str> = extensions.split(';').collect();
+        env::split_paths(&env::var_os("PATH")?)
+            .flat_map(|dir| {
+                extensions
+                    .iter()
+                    .map(move |ext| dir.join(format!("{}{}", name, ext)))
+            })
+            .find(|path| path.is_file())
+    }
+
+    fn program_name(command: &Command) -> String {
+        command.get_program().to_string_lossy().into_owned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn touch(path: &Path, content: &str) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+
+    #[test]
+    fn move_dir_refuses_existing_destination() {
+        let dir = tempfile::tempdir().unwrap();
+        let (src, dst) = (dir.path().join("src"), dir.path().join("a/b/dst"));
+        touch(&src.join("file"), "content");
+
+        move_dir(&src, &dst).unwrap();
+        assert!(!src.exists());
+        assert_eq!(fs::read_to_string(dst.join("file")).unwrap(), "content");
+
+        touch(&src.join("file"), "other");
+        match move_dir(&src, &dst) {
+            Err(Error::DestinationExists { path }) => assert_eq!(path, dst),
+            r => panic!("unexpected result: {:?}", r),
+        }
+        assert!(src.join("file").exists());
+    }
+
+    #[test]
+    fn copy_and_remove_moves_tree() {
+        let dir = tempfile::tempdir().unwrap();
+        let (src, dst) = (dir.path().join("src"), dir.path().join("dst"));
+        touch(&src.join("file"), "a");
+        touch(&src.join("sub/dir/file"), "b");
+        #[cfg(unix)]
+        std::os::unix::fs::symlink("sub/dir/file", src.join("link")).unwrap();
+
+        copy_and_remove(&src, &dst).unwrap();
+        assert!(!src.exists());
+        assert_eq!(fs::read_to_string(dst.join("file")).unwrap(), "a");
+        assert_eq!(fs::read_to_string(dst.join("sub/dir/file")).unwrap(), "b");
+        #[cfg(unix)]
+        assert_eq!(
+            fs::read_link(dst.join("link")).unwrap(),
+            Path::new("sub/dir/file")
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn status_with_stderr_keeps_raw_output() {
+        let (code, stderr) = process::status_with_stderr(
+            std::process::Command::new("sh")
+                .arg("-c")
+                .arg(r"printf '10%%\r100%%\n\377' >&2; exit 3"),
+        )
+        .unwrap();
+        assert_eq!(code, Some(3));
+        assert_eq!(stderr, "10%\r100%\n\u{fffd}");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn copy_and_remove_cleans_up_on_failure() {
+        let dir = tempfile::tempdir().unwrap();
+        let (src, dst) = (dir.path().join("src"), dir.path().join("dst"));
+        touch(&src.join("file"), "a");
+        // a socket cannot be copied as a regular file.
+        let _listener = std::os::unix::net::UnixListener::bind(src.join("socket")).unwrap();
+
+        assert!(copy_and_remove(&src, &dst).is_err());
+        assert!(!dst.exists());
+        assert!(src.join("file").exists());
+    }
 }
@@ -1,80 +1,860 @@
+mod backend;
 pub mod darcs;
+pub mod fossil;
 pub mod git;
 pub mod hg;
+pub mod jj;
+#[cfg(feature = "git2")]
+pub mod libgit2;
 pub mod pijul;
+pub mod svn;
 
-use crate::util::StrSkip;
-use anyhow::{anyhow, Result};
-use serde::{Deserialize, Serialize};
-use std::{ffi::OsStr, fmt::Display, path::Path, str::FromStr};
+pub use self::backend::{backends, register, VcsBackend};
 
-pub const POSSIBLE_VCS: &[&str] = &["git", "hg", "darcs", "pijul"];
+use crate::error::{Error, Result};
+use crate::query::Query;
+use crate::util::process;
+use chrono::{DateTime, Local};
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::{
+    collections::{BTreeMap, HashSet},
+    ffi::{OsStr, OsString},
+    fmt::{self, Display},
+    io,
+    path::{Path, PathBuf},
+    process::Command,
+    str::FromStr,
+    sync::{Arc, Mutex},
+};
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+/// The names of built-in VCSs.
+///
+/// Backends registered at runtime are not included; use `backends()` to enumerate all of them.
+pub const POSSIBLE_VCS: &[&str] = &["git", "hg", "darcs", "pijul", "fossil", "svn", "jj"];
+
+/// The identifier of a version control system.
+///
+/// In the cache file, the built-in VCSs are serialized as their variant names (e.g. `"Git"`),
+/// and the others are serialized as their names.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Vcs {
     Git,
     Hg,
     Darcs,
     Pijul,
+    Fossil,
+    Svn,
+    Jujutsu,
+    /// A VCS provided by a backend registered by `vcs::register`.
+    Custom(&'static str),
+}
+
+lazy_static! {
+    // Executables which have already been probed successfully.
+    static ref AVAILABLE: Mutex<HashSet<String>> = Mutex::new(HashSet::new());
+
+    // Names of custom VCSs read from the cache.
+    static ref INTERNED: Mutex<HashSet<&'static str>> = Mutex::new(HashSet::new());
+}
+
+/// The implementation used to operate repositories.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Backend {
+    /// Spawn the executable of VCS.
+    Command,
+    /// Use the library linked into rhq (only Git, with the feature `git2`).
+    Library,
+}
+
+impl FromStr for Backend {
+    type Err = String;
+
+    fn from_str(s: &str) -> ::std::result::Result<Backend, String> {
+        match s {
+            "command" => Ok(Backend::Command),
+            "library" => Ok(Backend::Library),
+            s => Err(format!("{} is invalid backend", s)),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Backend {
+    fn deserialize<D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> ::std::result::Result<Backend, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// Settings of the executable of a VCS, given by `[vcs.<name>]` in the configuration file.
+#[derive(Debug, Clone)]
+pub struct VcsConfig {
+    /// The implementation used to operate repositories.
+    pub backend: Backend,
+    /// The name or path of executable.
+    pub command: String,
+    /// Arguments always passed to the clone command.
+    pub clone_args: Vec<String>,
+    /// Environment variables set when running the executable.
+    pub env: BTreeMap<String, String>,
+    /// Whether the standard output of executable is redirected into the standard error,
+    /// so that the output of rhq is kept machine-readable.
+    pub stdout_to_stderr: bool,
+}
+
+impl VcsConfig {
+    /// Create the default settings of VCS.
+    pub fn new(vcs: Vcs) -> Self {
+        VcsConfig {
+            backend: if cfg!(feature = "git2") && vcs == Vcs::Git {
+                Backend::Library
+            } else {
+                Backend::Command
+            },
+            command: vcs.name().to_owned(),
+            clone_args: vec![],
+            env: BTreeMap::new(),
+            stdout_to_stderr: false,
+        }
+    }
+
+    /// Returns whether the operations are implemented by the library instead of the executable.
+    pub fn uses_library(&self) -> bool {
+        cfg!(feature = "git2") && self.backend == Backend::Library
+    }
+
+    /// Make a command to run the executable, whose standard I/O are inherited.
+    pub fn inherit(&self) -> Command {
+        let mut command = process::inherit(&self.command);
+        command.envs(&self.env);
+        if self.stdout_to_stderr {
+            command.stdout(io::stderr());
+        }
+        command
+    }
+
+    /// Make a command to run the executable, whose outputs are captured.
+    pub fn piped(&self) -> Command {
+        let mut command = process::piped(&self.command);
+        command.envs(&self.env);
+        command
+    }
 }
 
 impl Vcs {
-    pub fn do_init<P: AsRef<Path>>(self, path: P) -> Result<()> {
+    /// Returns the name of VCS, which is used in the command line and configuration.
+    pub fn name(self) -> &'static str {
         match self {
-            Vcs::Git => git::init(path),
-            Vcs::Hg => hg::init(path),
-            Vcs::Darcs => darcs::initialize(path),
-            Vcs::Pijul => pijul::init(path),
+            Vcs::Git => "git",
+            Vcs::Hg => "hg",
+            Vcs::Darcs => "darcs",
+            Vcs::Pijul => "pijul",
+            Vcs::Fossil => "fossil",
+            Vcs::Svn => "svn",
+            Vcs::Jujutsu => "jj",
+            Vcs::Custom(name) => name,
         }
     }
 
-    pub fn do_clone<P, U, I, S>(self, path: P, url: U, args: I) -> Result<()>
+    /// Returns the identifier used in the cache file.
+    fn id(self) -> &'static str {
+        match self {
+            Vcs::Git => "Git",
+            Vcs::Hg => "Hg",
+            Vcs::Darcs => "Darcs",
+            Vcs::Pijul => "Pijul",
+            Vcs::Fossil => "Fossil",
+            Vcs::Svn => "Svn",
+            Vcs::Jujutsu => "Jujutsu",
+            Vcs::Custom(name) => name,
+        }
+    }
+
+    fn from_id(id: &str) -> Vcs {
+        match id {
+            "Git" => Vcs::Git,
+            "Hg" => Vcs::Hg,
+            "Darcs" => Vcs::Darcs,
+            "Pijul" => Vcs::Pijul,
+            "Fossil" => Vcs::Fossil,
+            "Svn" => Vcs::Svn,
+            "Jujutsu" => Vcs::Jujutsu,
+            name => {
+                let mut interned = INTERNED.lock().unwrap_or_else(|e| e.into_inner());
+                match interned.get(name) {
+                    Some(name) => Vcs::Custom(name),
+                    None => {
+                        let name: &'static str = Box::leak(name.to_owned().into_boxed_str());
+                        interned.insert(name);
+                        Vcs::Custom(name)
+                    }
+                }
+            }
+        }
+    }
+
+    /// Returns the backend registered for this VCS.
+    pub fn backend(self) -> Result<Arc<dyn VcsBackend>> {
+        backend::find(self).ok_or(Error::VcsNotRegistered { vcs: self })
+    }
+
+    /// Returns the minimum version of executable supported by rhq.
+    pub fn minimum_version(self) -> Option<Version> {
+        self.backend().ok()?.minimum_version()
+    }
+
+    /// Look up the executable of VCS and check whether its version is supported.
+    pub fn probe(self, config: &VcsConfig) -> Result<Capability> {
+        let backend = self.backend()?;
+        let command = config.command.as_str();
+        let output = process::output(config.piped().arg("--version")).map_err(|err| match err {
+            Error::VcsNotFound {
+                command, source, ..
+            } => Error::VcsNotFound {
+                command,
+                vcs: Some(self),
+                source,
+            },
+            err => err,
+        })?;
+        if !output.status.success() {
+            return Err(Error::VcsFailed {
+                command: command.to_owned(),
+                code: output.status.code(),
+            });
+        }
+
+        let version = Version::parse(&String::from_utf8_lossy(&output.stdout));
+        if let (Some(version), Some(required)) = (version, backend.minimum_version()) {
+            if version < required {
+                return Err(Error::VcsTooOld {
+                    vcs: self,
+                    version,
+                    required,
+                });
+            }
+        }
+
+        Ok(Capability {
+            vcs: self,
+            command: command.to_owned(),
+            path: process::find_executable(command),
+            version,
+        })
+    }
+
+    /// Look up the backend, and probe the executable before running an operation.
+    ///
+    /// The result of probing is remembered, so that the executable is probed at most once.
+    pub(crate) fn prepare(self, config: &VcsConfig) -> Result<Arc<dyn VcsBackend>> {
+        let backend = self.backend()?;
+        if !config.uses_library() {
+            let mut available = AVAILABLE.lock().unwrap_or_else(|e| e.into_inner());
+            if !available.contains(&config.command) {
+                self.probe(config)?;
+                available.insert(config.command.clone());
+            }
+        }
+        Ok(backend)
+    }
+
+    pub fn do_init<P: AsRef<Path>>(self, config: &VcsConfig, path: P) -> Result<()> {
+        self.prepare(config)?.init(config, path.as_ref())
+    }
+
+    pub fn do_clone<P, U, I, S>(self, config: &VcsConfig, path: P, url: U, args: I) -> Result<()>
     where
         P: AsRef<Path>,
         U: AsRef<str>,
         I: IntoIterator<Item = S>,
         S: AsRef<OsStr> + Display,
     {
-        match self {
-            Vcs::Git => git::clone(url, path, args),
-            Vcs::Hg => hg::clone(url, path, args),
-            Vcs::Darcs => darcs::clone(url, path, args),
-            Vcs::Pijul => pijul::clone(url, path, args),
-        }
+        let args: Vec<OsString> = args
+            .into_iter()
+            .map(|arg| arg.as_ref().to_owned())
+            .collect();
+        self.prepare(config)?
+            .clone_repository(config, url.as_ref(), path.as_ref(), &args)
     }
 
-    pub fn get_remote_url<P: AsRef<Path>>(self, path: P) -> Result<Option<String>> {
-        match self {
-            Vcs::Git => git::get_remote_url(path),
-            Vcs::Hg => hg::get_remote_url(path),
-            _ => Err(anyhow!("This VCS has not supported yet")),
+    pub fn get_remote_url<P: AsRef<Path>>(
+        self,
+        config: &VcsConfig,
+        path: P,
+    ) -> Result<Option<String>> {
+        self.prepare(config)?.get_remote_url(config, path.as_ref())
+    }
+
+    pub fn set_remote_url(self, config: &VcsConfig, path: &Path, url: &str) -> Result<()> {
+        self.prepare(config)?.set_remote_url(config, path, url)
+    }
+
+    /// Returns the summary of local changes in the repository.
+    pub fn status<P: AsRef<Path>>(self, config: &VcsConfig, path: P) -> Result<Status> {
+        self.prepare(config)?.status(config, path.as_ref())
+    }
+
+    /// Download objects and refs from the remote repository, without updating the working directory.
+    pub fn fetch<P: AsRef<Path>>(self, config: &VcsConfig, path: P) -> Result<()> {
+        self.prepare(config)?.fetch(config, path.as_ref())
+    }
+
+    /// Returns the time of the latest commit in the repository.
+    pub fn last_commit_time<P: AsRef<Path>>(
+        self,
+        config: &VcsConfig,
+        path: P,
+    ) -> Result<Option<DateTime<Local>>> {
+        self.prepare(config)?
+            .last_commit_time(config, path.as_ref())
+    }
+
+    /// Returns the number of branches, stashes and so on which exist only in the local repository.
+    pub fn local_only_refs<P: AsRef<Path>>(self, config: &VcsConfig, path: P) -> Result<usize> {
+        self.prepare(config)?.local_only_refs(config, path.as_ref())
+    }
+
+    /// Returns the number of ignored files (or directories) in the working directory.
+    pub fn ignored_files<P: AsRef<Path>>(self, config: &VcsConfig, path: P) -> Result<usize> {
+        self.prepare(config)?.ignored_files(config, path.as_ref())
+    }
+
+    /// Returns the paths of working directories linked to the repository, other than itself.
+    ///
+    /// The executable is not probed here, since most VCSs have no linked working directories.
+    pub fn linked_worktrees<P: AsRef<Path>>(
+        self,
+        config: &VcsConfig,
+        path: P,
+    ) -> Result<Vec<PathBuf>> {
+        self.backend()?.linked_worktrees(config, path.as_ref())
+    }
+
+    /// Returns the default branch of the remote repository.
+    pub fn default_branch<P: AsRef<Path>>(
+        self,
+        config: &VcsConfig,
+        path: P,
+    ) -> Result<Option<String>> {
+        self.prepare(config)?.default_branch(config, path.as_ref())
+    }
+}
+
+impl fmt::Display for Vcs {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.id())
+    }
+}
+
+impl Serialize for Vcs {
+    fn serialize<S: Serializer>(&self, serializer: S) -> ::std::result::Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.id())
+    }
+}
+
+impl<'de> Deserialize<'de> for Vcs {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> ::std::result::Result<Vcs, D::Error> {
+        let id = String::deserialize(deserializer)?;
+        Ok(Vcs::from_id(&id))
+    }
+}
+
+/// Options of cloning, which are translated into the arguments of each VCS.
+#[derive(Debug, Clone, Default)]
+pub struct CloneOptions {
+    /// Create a shallow clone with the history truncated to the specified number of commits.
+    pub depth: Option<u32>,
+    /// The branch (or channel) to check out.
+    pub branch: Option<String>,
+    /// Clone submodules recursively.
+    pub recursive: bool,
+    /// Clone without working directory.
+    pub bare: bool,
+    /// Extra arguments passed to the clone command as is.
+    pub extra_args: Vec<String>,
+}
+
+impl CloneOptions {
+    /// Build the arguments of clone command for `vcs`.
+    ///
+    /// The arguments are ordered as the translated options and then `extra_args`.
+    /// Options which `vcs` cannot handle are reported as `Error::Unsupported`,
+    /// except `recursive` which is ignored if the VCS has no notion of submodules
+    /// (Mercurial clones subrepositories by default).
+    pub fn to_args(&self, vcs: Vcs) -> Result<Vec<String>> {
+        let mut args = vcs.backend()?.clone_args(self)?;
+        args.extend(self.extra_args.iter().cloned());
+        Ok(args)
+    }
+}
+
+/// The summary of local changes in a repository.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Status {
+    /// The number of changed (or untracked) files in the working directory.
+    pub changed: usize,
+    /// The number of local commits not pushed to the upstream, or `None` if it is unknown.
+    pub ahead: Option<usize>,
+}
+
+impl Status {
+    /// Returns `true` if there are neither uncommitted changes nor unpushed commits.
+    pub fn is_clean(&self) -> bool {
+        self.changed == 0 && self.ahead.unwrap_or(0) == 0
+    }
+}
+
+/// The executable of VCS found by `Vcs::probe`.
+#[derive(Debug, Clone)]
+pub struct Capability {
+    pub vcs: Vcs,
+    pub command: String,
+    /// The location of executable, if it is found in `PATH`.
+    pub path: Option<PathBuf>,
+    /// The version of executable, if it could be parsed from `--version`.
+    pub version: Option<Version>,
+}
+
+/// The version number of VCS executable.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Version {
+    pub major: u32,
+    pub minor: u32,
+    pub patch: u32,
+}
+
+impl Version {
+    pub fn new(major: u32, minor: u32, patch: u32) -> Self {
+        Version {
+            major,
+            minor,
+            patch,
         }
     }
 
-    pub fn set_remote_url(self, path: &Path, url: &str) -> Result<()> {
-        match self {
-            Vcs::Git => git::set_remote(path, url),
-            _ => Err(anyhow!("This VCS has not supported yet")),
+    /// Extract the first version number from the output of `--version`.
+    pub fn parse(s: &str) -> Option<Version> {
+        lazy_static! {
+            static ref RE_VERSION: Regex = Regex::new(r"\b(\d+)\.(\d+)(?:\.(\d+))?").unwrap();
         }
+        let cap = RE_VERSION.captures(s)?;
+        let number = |i: usize| cap.get(i).map_or(Some(0), |m| m.as_str().parse().ok());
+        Some(Version::new(number(1)?, number(2)?, number(3)?))
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
     }
 }
 
+/// Run the clone command, and classify the failure by `not_found`.
+///
+/// The command runs in the C locale, so that the messages matched by `not_found` are not translated.
+fn run_clone<F>(config: &VcsConfig, command: &mut Command, url: &str, not_found: F) -> Result<()>
+where
+    F: Fn(&str) -> bool,
+{
+    command.env("LC_ALL", "C");
+    let result = process::status_with_stderr(command)?;
+    check_clone_result(&config.command, url, result, not_found)
+}
+
+/// Check the result of clone command, and classify the failure.
+fn check_clone_result<F>(
+    command: &str,
+    url: &str,
+    (code, stderr): (Option<i32>, String),
+    not_found: F,
+) -> Result<()>
+where
+    F: Fn(&str) -> bool,
+{
+    if code == Some(0) {
+        return Ok(());
+    }
+    if not_found(&stderr) {
+        Err(Error::RemoteNotFound {
+            url: url.to_owned(),
+        })
+    } else {
+        Err(Error::VcsFailed {
+            command: command.to_owned(),
+            code,
+        })
+    }
+}
+
+/// Detect the VCS of working directory located at `path`, by using the registered backends.
 pub fn detect_from_path<P: AsRef<Path>>(path: P) -> Option<Vcs> {
-    [".git", ".hg", "_darcs", ".pijul"]
+    backend::detect(path.as_ref())
+}
+
+/// Guess the VCS of remote repository from the scheme, host and path of `url`.
+pub fn guess_from_url(url: &str) -> Option<Vcs> {
+    lazy_static! {
+        static ref KNOWN_HOSTS: Vec<(&'static str, Vcs)> = vec![
+            ("github.com", Vcs::Git),
+            ("gitlab.com", Vcs::Git),
+            ("bitbucket.org", Vcs::Git),
+            ("codeberg.org", Vcs::Git),
+            ("git.sr.ht", Vcs::Git),
+            ("hg.sr.ht", Vcs::Hg),
+            ("foss.heptapod.net", Vcs::Hg),
+            ("hub.darcs.net", Vcs::Darcs),
+            ("nest.pijul.com", Vcs::Pijul),
+            ("chiselapp.com", Vcs::Fossil),
+        ];
+        static ref HOST_PREFIXES: Vec<(&'static str, Vcs)> = vec![
+            ("git.", Vcs::Git),
+            ("hg.", Vcs::Hg),
+            ("darcs.", Vcs::Darcs),
+            ("fossil.", Vcs::Fossil),
+            ("svn.", Vcs::Svn),
+        ];
+        static ref PATH_SUFFIXES: Vec<(&'static str, Vcs)> = vec![
+            (".git", Vcs::Git),
+            (".hg", Vcs::Hg),
+            (".fossil", Vcs::Fossil)
+        ];
+    }
+
+    let query = url.parse::<Query>().ok()?;
+    let (host, path) = match query {
+        Query::Url(ref url) if url.scheme() == "git" => return Some(Vcs::Git),
+        Query::Url(ref url) => (url.host_str().unwrap_or(""), url.path()),
+        // The SCP-like syntax is used only by Git.
+        Query::Scp(..) => return Some(Vcs::Git),
+        Query::Path(..) => return None,
+    };
+    let host = host.to_lowercase();
+    let path = path.trim_end_matches('/');
+
+    // The suffix of path is more specific than the prefix of host (e.g. `hg.example.org/foo.git`).
+    KNOWN_HOSTS
         .iter()
-        .find(|vcs| path.as_ref().join(vcs).exists())
-        .and_then(|s| s.skip(1).parse().ok())
+        .find(|&&(known, _)| known == host)
+        .or_else(|| {
+            PATH_SUFFIXES
+                .iter()
+                .find(|&&(suffix, _)| path.ends_with(suffix))
+        })
+        .or_else(|| {
+            HOST_PREFIXES
+                .iter()
+                .find(|&&(prefix, _)| host.starts_with(prefix))
+        })
+        .map(|&(_, vcs)| vcs)
+}
+
+/// Determine the VCS of remote repository at `url`.
+///
+/// The VCS is guessed from `url` first.
+/// If there is no hint, each backend checks whether it can access the repository.
+/// If none of the backends could check it (e.g. the executables are missing), the first error is returned.
+pub fn detect_from_url<F>(url: &str, config: F) -> Result<Vcs>
+where
+    F: Fn(Vcs) -> VcsConfig,
+{
+    if let Some(vcs) = guess_from_url(url) {
+        return Ok(vcs);
+    }
+    let mut candidates = Vec::new();
+    let mut checked = false;
+    let mut error = None;
+    for backend in backends() {
+        match backend.is_remote_repository(&config(backend.vcs()), url) {
+            Ok(found) => {
+                checked = true;
+                if found {
+                    candidates.push(backend.vcs());
+                }
+            }
+            Err(Error::Unsupported { .. }) => {}
+            Err(err) => {
+                error.get_or_insert(err);
+            }
+        }
+    }
+    match (&candidates[..], error) {
+        ([vcs], _) => Ok(*vcs),
+        ([], Some(err)) if !checked => Err(err),
+        _ => Err(Error::VcsUndetermined {
+            url: url.to_owned(),
+            candidates,
+        }),
+    }
 }
 
 impl FromStr for Vcs {
     type Err = String;
     fn from_str(s: &str) -> ::std::result::Result<Vcs, String> {
-        match s {
-            "git" => Ok(Vcs::Git),
-            "hg" => Ok(Vcs::Hg),
-            "darcs" => Ok(Vcs::Darcs),
-            "pijul" => Ok(Vcs::Pijul),
-            s => Err(format!("{} is invalid string", s)),
+        backends()
+            .iter()
+            .map(|backend| backend.vcs())
+            .find(|vcs| vcs.name() == s)
+            .ok_or_else(|| format!("{} is invalid string", s))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn parse_version() {
+        let cases = &[
+            ("git version 2.39.2\n", Version::new(2, 39, 2)),
+            ("git version 2.37.1 (Apple Git-137.1)\n", Version::new(2, 37, 1)),
+            (
+                "Mercurial Distributed SCM (version 6.3.2)\n(see https://mercurial-scm.org for more information)\n",
+                Version::new(6, 3, 2),
+            ),
+            ("2.16.5 (release)\n", Version::new(2, 16, 5)),
+            ("pijul 1.0.0-beta.9\n", Version::new(1, 0, 0)),
+            ("hg 4.5\n", Version::new(4, 5, 0)),
+        ];
+        for &(s, version) in cases {
+            assert_eq!(Version::parse(s), Some(version), "{}", s);
+        }
+        assert_eq!(Version::parse("unknown"), None);
+        assert!(Version::new(1, 9, 5) < Version::new(2, 0, 0));
+    }
+
+    #[test]
+    fn clone_options_to_args() {
+        let options = CloneOptions {
+            depth: Some(1),
+            branch: Some("release".into()),
+            recursive: true,
+            bare: false,
+            extra_args: vec!["--filter=blob:none".into()],
+        };
+        assert_eq!(
+            options.to_args(Vcs::Git).unwrap(),
+            [
+                "--depth",
+                "1",
+                "--branch",
+                "release",
+                "--recurse-submodules",
+                "--filter=blob:none"
+            ]
+        );
+        match options.to_args(Vcs::Hg) {
+            Err(Error::Unsupported { vcs: Vcs::Hg, .. }) => {}
+            r => panic!("unexpected result: {:?}", r),
+        }
+
+        let options = CloneOptions {
+            branch: Some("main".into()),
+            recursive: true,
+            bare: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            options.to_args(Vcs::Hg).unwrap(),
+            ["--branch", "main", "--noupdate"]
+        );
+        assert!(options.to_args(Vcs::Pijul).is_err());
+    }
+
+    #[test]
+    fn clone_command_order() {
+        for &vcs in &[Vcs::Git, Vcs::Hg, Vcs::Darcs, Vcs::Pijul] {
+            let command = vcs.backend().unwrap().clone_command(
+                &VcsConfig::new(vcs),
+                "https://example.com/foo/bar",
+                Path::new("/tmp/rhq"),
+                &["--opt".into(), "value".into()],
+            );
+            assert_eq!(command.get_program(), vcs.name());
+            let args: Vec<_> = command.get_args().collect();
+            assert_eq!(
+                args,
+                [
+                    "clone",
+                    "--opt",
+                    "value",
+                    "https://example.com/foo/bar",
+                    "/tmp/rhq"
+                ],
+                "{:?}",
+                vcs
+            );
+        }
+    }
+
+    #[test]
+    fn clone_result_not_found() {
+        let stderr = "remote: Repository not found.\nfatal: repository 'https://github.com/foo/bar.git/' not found\n";
+        match check_clone_result(
+            "git",
+            "https://github.com/foo/bar.git",
+            (Some(128), stderr.into()),
+            |message| git::GitBackend.remote_not_found(message),
+        ) {
+            Err(Error::RemoteNotFound { url }) => assert_eq!(url, "https://github.com/foo/bar.git"),
+            r => panic!("unexpected result: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn clone_result_network_failure() {
+        let stderr = "fatal: unable to access 'https://github.com/foo/bar.git/': Could not resolve host: github.com\n";
+        match check_clone_result(
+            "git",
+            "https://github.com/foo/bar.git",
+            (Some(128), stderr.into()),
+            |message| git::GitBackend.remote_not_found(message),
+        ) {
+            Err(Error::VcsFailed { code, .. }) => assert_eq!(code, Some(128)),
+            r => panic!("unexpected result: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn remote_not_found_messages() {
+        let not_found = |vcs: Vcs, message: &str| vcs.backend().unwrap().remote_not_found(message);
+        assert!(not_found(
+            Vcs::Git,
+            "fatal: 'foo/bar' does not appear to be a git repository\nfatal: Could not read from remote repository.\n"
+        ));
+        assert!(not_found(
+            Vcs::Git,
+            "ERROR: Repository not found.\nfatal: Could not read from remote repository.\n"
+        ));
+        assert!(!not_found(
+            Vcs::Git,
+            "warning: Could not find remote branch dev to clone.\nfatal: Remote branch dev not found in upstream origin\n"
+        ));
+        assert!(not_found(Vcs::Hg, "abort: repository /tmp/foo not found\n"));
+        assert!(not_found(Vcs::Hg, "abort: HTTP Error 404: Not Found\n"));
+        assert!(!not_found(
+            Vcs::Hg,
+            "abort: error: Name or service not known\n"
+        ));
+        assert!(not_found(
+            Vcs::Svn,
+            "svn: E170000: URL 'https://example.com/svn/foo' doesn't exist\n"
+        ));
+        assert!(!not_found(Vcs::Pijul, "Error: Repository not found\n"));
+    }
+
+    struct WrapBackend;
+
+    impl VcsBackend for WrapBackend {
+        fn vcs(&self) -> Vcs {
+            Vcs::Custom("wrap")
+        }
+
+        fn detect(&self, path: &Path) -> bool {
+            path.join(".wrap").exists()
+        }
+
+        fn init(&self, _config: &VcsConfig, path: &Path) -> Result<()> {
+            fs::create_dir_all(path.join(".wrap"))?;
+            Ok(())
+        }
+
+        fn clone_command(
+            &self,
+            config: &VcsConfig,
+            url: &str,
+            path: &Path,
+            args: &[OsString],
+        ) -> Command {
+            let mut command = config.inherit();
+            command.arg("get").args(args).arg(url).arg(path);
+            command
+        }
+    }
+
+    #[test]
+    fn serialize_builtin_vcs() {
+        assert_eq!(serde_json::to_string(&Vcs::Git).unwrap(), r#""Git""#);
+        let vcs: Vcs = serde_json::from_str(r#""Pijul""#).unwrap();
+        assert_eq!(vcs, Vcs::Pijul);
+    }
+
+    #[test]
+    fn register_custom_backend() {
+        register(WrapBackend);
+        let vcs = Vcs::Custom("wrap");
+
+        assert_eq!("wrap".parse::<Vcs>(), Ok(vcs));
+        assert_eq!(serde_json::to_string(&vcs).unwrap(), r#""wrap""#);
+        let deserialized: Vcs = serde_json::from_str(r#""wrap""#).unwrap();
+        assert_eq!(deserialized, vcs);
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("repo");
+        let backend = vcs.backend().unwrap();
+        let config = VcsConfig::new(vcs);
+        backend.init(&config, &path).unwrap();
+        assert_eq!(detect_from_path(&path), Some(vcs));
+
+        match backend.status(&config, &path) {
+            Err(Error::Unsupported { .. }) => {}
+            r => panic!("unexpected result: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn guess_vcs_from_url() {
+        let cases = &[
+            ("https://github.com/ubnt-intrepid/rhq", Some(Vcs::Git)),
+            ("git@example.com:foo/bar", Some(Vcs::Git)),
+            ("git://example.com/foo/bar", Some(Vcs::Git)),
+            ("https://hg.example.org/proj", Some(Vcs::Hg)),
+            ("https://hg.sr.ht/~foo/bar", Some(Vcs::Hg)),
+            ("https://hg.example.org/foo.git", Some(Vcs::Git)),
+            ("https://git.example.org/foo.hg", Some(Vcs::Hg)),
+            ("https://example.com/repos/proj.hg/", Some(Vcs::Hg)),
+            ("https://hub.darcs.net/foo/bar", Some(Vcs::Darcs)),
+            ("https://nest.pijul.com/foo/bar", Some(Vcs::Pijul)),
+            ("https://svn.example.org/repos/trunk", Some(Vcs::Svn)),
+            ("file:///srv/repos/proj.git", Some(Vcs::Git)),
+            ("https://example.com/foo/bar", None),
+            ("file:///srv/repos/proj", None),
+        ];
+        for &(url, expected) in cases {
+            assert_eq!(guess_from_url(url), expected, "{}", url);
+        }
+    }
+
+    #[test]
+    fn detect_local_git_repository() {
+        let dir = tempfile::tempdir().unwrap();
+        let upstream = dir.path().join("proj");
+        let st = Command::new("git")
+            .args(["init", "--quiet", "--bare"])
+            .arg(&upstream)
+            .status()
+            .expect("git is required to run this test");
+        assert!(st.success());
+
+        let url = format!("file://{}", upstream.display());
+        assert_eq!(detect_from_url(&url, VcsConfig::new).unwrap(), Vcs::Git);
+
+        let missing = format!("file://{}", dir.path().join("missing").display());
+        match detect_from_url(&missing, VcsConfig::new) {
+            Err(Error::VcsUndetermined { candidates, .. }) => assert!(candidates.is_empty()),
+            r => panic!("unexpected result: {:?}", r),
+        }
+
+        let missing_executable = |vcs| VcsConfig {
+            command: "rhq-missing-command".to_owned(),
+            ..VcsConfig::new(vcs)
+        };
+        match detect_from_url(&url, missing_executable) {
+            Err(Error::VcsNotFound { command, .. }) => assert_eq!(command, "rhq-missing-command"),
+            r => panic!("unexpected result: {:?}", r),
         }
     }
 }
@@ -0,0 +1,196 @@
+//! Defines the interface of VCS backends, and the registry of them.
+
+use super::{
+    darcs, fossil, git, hg, jj, pijul, svn, CloneOptions, Status, Vcs, VcsConfig, Version,
+};
+use crate::error::{Error, Result};
+use chrono::{DateTime, Local};
+use lazy_static::lazy_static;
+use std::{
+    ffi::OsString,
+    path::{Path, PathBuf},
+    process::Command,
+    sync::{Arc, RwLock},
+};
+
+/// The implementation of operations of a VCS.
+///
+/// Operations other than initialization and cloning are optional, and return
+/// `Error::Unsupported` by default.
+pub trait VcsBackend: Send + Sync {
+    /// Returns the identifier of VCS handled by this backend.
+    fn vcs(&self) -> Vcs;
+
+    /// Returns whether `path` is the root of a working directory of this VCS.
+    fn detect(&self, path: &Path) -> bool;
+
+    /// Returns the minimum version of executable supported by this backend.
+    fn minimum_version(&self) -> Option<Version> {
+        None
+    }
+
+    /// Create an empty repository at `path`.
+    fn init(&self, config: &VcsConfig, path: &Path) -> Result<()>;
+
+    /// Build the command to clone `url` into `path`.
+    ///
+    /// `args` should be placed between the subcommand and the positional arguments.
+    fn clone_command(
+        &self,
+        config: &VcsConfig,
+        url: &str,
+        path: &Path,
+        args: &[OsString],
+    ) -> Command;
+
+    /// Clone `url` into `path`, by running the command built by `clone_command`.
+    fn clone_repository(
+        &self,
+        config: &VcsConfig,
+        url: &str,
+        path: &Path,
+        args: &[OsString],
+    ) -> Result<()> {
+        let mut command = self.clone_command(config, url, path, args);
+        super::run_clone(config, &mut command, url, |message| {
+            self.remote_not_found(message)
+        })
+    }
+
+    /// Returns whether the error message of the clone command, printed in the C locale, tells
+    /// that the remote repository does not exist.
+    fn remote_not_found(&self, _message: &str) -> bool {
+        false
+    }
+
+    /// Translate the options of cloning (except `extra_args`) into the arguments of clone command.
+    fn clone_args(&self, options: &CloneOptions) -> Result<Vec<String>> {
+        if options.depth.is_some() {
+            return Err(unsupported(self.vcs(), "cloning with --depth"));
+        }
+        if options.branch.is_some() {
+            return Err(unsupported(self.vcs(), "cloning with --branch"));
+        }
+        if options.bare {
+            return Err(unsupported(self.vcs(), "cloning with --bare"));
+        }
+        Ok(vec![])
+    }
+
+    /// Returns whether `url` refers to a repository of this VCS, by accessing it.
+    ///
+    /// This is used to determine the VCS when cloning.
+    /// An error is returned if the repository cannot be checked at all (e.g. the executable is missing).
+    fn is_remote_repository(&self, _config: &VcsConfig, _url: &str) -> Result<bool> {
+        Err(unsupported(self.vcs(), "detecting remote repository"))
+    }
+
+    /// Returns the URL of remote repository which the working directory tracks.
+    fn get_remote_url(&self, _config: &VcsConfig, _path: &Path) -> Result<Option<String>> {
+        Err(unsupported(self.vcs(), "getting remote URL"))
+    }
+
+    /// Change the URL of remote repository which the working directory tracks.
+    fn set_remote_url(&self, _config: &VcsConfig, _path: &Path, _url: &str) -> Result<()> {
+        Err(unsupported(self.vcs(), "setting remote URL"))
+    }
+
+    /// Returns the summary of local changes.
+    fn status(&self, _config: &VcsConfig, _path: &Path) -> Result<Status> {
+        Err(unsupported(self.vcs(), "getting status"))
+    }
+
+    /// Download objects and refs from the remote repository, without updating the working directory.
+    fn fetch(&self, _config: &VcsConfig, _path: &Path) -> Result<()> {
+        Err(unsupported(self.vcs(), "fetching"))
+    }
+
+    /// Returns the time of the latest commit in the working directory, or `None` if there are no commits.
+    fn last_commit_time(
+        &self,
+        _config: &VcsConfig,
+        _path: &Path,
+    ) -> Result<Option<DateTime<Local>>> {
+        Err(unsupported(self.vcs(), "getting the last commit"))
+    }
+
+    /// Returns the default branch of the remote repository.
+    fn default_branch(&self, _config: &VcsConfig, _path: &Path) -> Result<Option<String>> {
+        Err(unsupported(self.vcs(), "getting the default branch"))
+    }
+
+    /// Returns the number of branches, stashes and so on which exist only in the local repository,
+    /// other than the current branch checked by `status`.
+    fn local_only_refs(&self, _config: &VcsConfig, _path: &Path) -> Result<usize> {
+        Err(unsupported(self.vcs(), "finding local-only branches"))
+    }
+
+    /// Returns the number of ignored files (or directories) in the working directory,
+    /// which `status` does not count.
+    fn ignored_files(&self, _config: &VcsConfig, _path: &Path) -> Result<usize> {
+        Err(unsupported(self.vcs(), "finding ignored files"))
+    }
+
+    /// Returns the paths of working directories linked to the repository at `path`, other than itself.
+    ///
+    /// This returns an empty list by default, for VCSs without such working directories.
+    fn linked_worktrees(&self, _config: &VcsConfig, _path: &Path) -> Result<Vec<PathBuf>> {
+        Ok(vec![])
+    }
+}
+
+fn unsupported(vcs: Vcs, operation: &'static str) -> Error {
+    Error::Unsupported { vcs, operation }
+}
+
+lazy_static! {
+    // Jujutsu precedes Git, since colocated repositories also have `.git`.
+    static ref REGISTRY: RwLock<Vec<Arc<dyn VcsBackend>>> = RwLock::new(vec![
+        Arc::new(jj::JjBackend),
+        Arc::new(git::GitBackend),
+        Arc::new(hg::HgBackend),
+        Arc::new(darcs::DarcsBackend),
+        Arc::new(pijul::PijulBackend),
+        Arc::new(fossil::FossilBackend),
+        Arc::new(svn::SvnBackend),
+    ]);
+}
+
+/// Register a backend.
+///
+/// The registered backend replaces the existing one for the same VCS,
+/// and takes precedence over built-in backends when detecting the VCS of a directory.
+pub fn register<B: VcsBackend + 'static>(backend: B) {
+    let mut registry = REGISTRY.write().unwrap_or_else(|e| e.into_inner());
+    let vcs = backend.vcs();
+    registry.retain(|b| b.vcs() != vcs);
+    registry.insert(0, Arc::new(backend));
+}
+
+/// Returns the registered backends, in the order of precedence.
+pub fn backends() -> Vec<Arc<dyn VcsBackend>> {
+    REGISTRY.read().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+/// Returns the backend registered for `vcs`.
+pub fn find(vcs: Vcs) -> Option<Arc<dyn VcsBackend>> {
+    REGISTRY
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+        .iter()
+        .find(|backend| backend.vcs() == vcs)
+        .cloned()
+}
+
+/// Returns the first registered backend which detects a working directory at `path`.
+///
+/// Unlike `backends()`, this does not clone the registry, as it is called for every directory
+/// while scanning.
+pub(crate) fn detect(path: &Path) -> Option<Vcs> {
+    REGISTRY
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+        .iter()
+        .find(|backend| backend.detect(path))
+        .map(|backend| backend.vcs())
+}
@@ -1,44 +1,102 @@
+use super::{CloneOptions, Vcs, VcsBackend, VcsConfig, Version};
+use crate::error::{Error, Result};
 use crate::util::process;
-use anyhow::{anyhow, Result};
-use std::{ffi::OsStr, path::Path};
+use lazy_static::lazy_static;
+use regex::Regex;
+use std::{
+    ffi::{OsStr, OsString},
+    path::Path,
+    process::Command,
+};
 
-pub fn initialize<P>(path: P) -> Result<()>
+pub fn initialize<P>(config: &VcsConfig, path: P) -> Result<()>
 where
     P: AsRef<Path>,
 {
-    process::inherit("darcs")
-        .arg("initialize")
-        .arg(path.as_ref().as_os_str())
-        .status()
-        .map_err(Into::into)
-        .and_then(|st| match st.code() {
-            Some(0) => Ok(()),
-            st => Err(anyhow!(
-                "command 'darcs' is exited with return code {:?}.",
-                st
-            )),
-        })
+    process::status(
+        config
+            .inherit()
+            .arg("initialize")
+            .arg(path.as_ref().as_os_str()),
+    )
 }
 
-pub fn clone<P, U, I, S>(url: U, path: P, args: I) -> Result<()>
+/// Build the command to clone `url` into `path`.
+///
+/// `args` are placed between the subcommand and the positional arguments.
+pub fn clone_command<P, U, I, S>(config: &VcsConfig, url: U, path: P, args: I) -> Command
 where
     P: AsRef<Path>,
     U: AsRef<str>,
     I: IntoIterator<Item = S>,
     S: AsRef<OsStr>,
 {
-    let path = format!("{}", path.as_ref().display());
-    process::inherit("darcs")
+    let mut command = config.inherit();
+    command
         .arg("clone")
         .args(args)
-        .args(&[url.as_ref(), &path])
-        .status()
-        .map_err(Into::into)
-        .and_then(|st| match st.code() {
-            Some(0) => Ok(()),
-            st => Err(anyhow!(
-                "command 'darcs' is exited with return code {:?}.",
-                st
-            )),
-        })
+        .arg(url.as_ref())
+        .arg(path.as_ref());
+    command
+}
+
+/// The backend of Darcs.
+pub struct DarcsBackend;
+
+impl VcsBackend for DarcsBackend {
+    fn vcs(&self) -> Vcs {
+        Vcs::Darcs
+    }
+
+    /// Matches the messages of `darcs clone` in the C locale.
+    fn remote_not_found(&self, message: &str) -> bool {
+        lazy_static! {
+            static ref RE_NOT_FOUND: Regex =
+                Regex::new(r"(?m)^darcs failed:\s+Not a repository: ").unwrap();
+        }
+        RE_NOT_FOUND.is_match(message)
+    }
+
+    fn detect(&self, path: &Path) -> bool {
+        path.join("_darcs").exists()
+    }
+
+    fn minimum_version(&self) -> Option<Version> {
+        // `darcs clone` was introduced in 2.8.
+        Some(Version::new(2, 8, 0))
+    }
+
+    fn init(&self, config: &VcsConfig, path: &Path) -> Result<()> {
+        initialize(config, path)
+    }
+
+    fn clone_command(
+        &self,
+        config: &VcsConfig,
+        url: &str,
+        path: &Path,
+        args: &[OsString],
+    ) -> Command {
+        clone_command(config, url, path, args)
+    }
+
+    fn clone_args(&self, options: &CloneOptions) -> Result<Vec<String>> {
+        let unsupported = |operation| {
+            Err(Error::Unsupported {
+                vcs: Vcs::Darcs,
+                operation,
+            })
+        };
+        if options.depth.is_some() {
+            return unsupported("cloning with --depth");
+        }
+        if options.branch.is_some() {
+            return unsupported("cloning with --branch");
+        }
+        let mut args = vec![];
+        if options.bare {
+            args.push("--no-working-dir".to_owned());
+        }
+        Ok(args)
+    }
 }
@@ -0,0 +1,206 @@
+use super::{Status, Vcs, VcsBackend, VcsConfig};
+use crate::error::{Error, Result};
+use crate::util::process;
+use lazy_static::lazy_static;
+use regex::Regex;
+use std::{
+    ffi::{OsStr, OsString},
+    fs,
+    path::Path,
+    process::Command,
+};
+
+/// The name of repository file, which is placed in the root of check-out.
+///
+/// Fossil keeps the repository outside of the check-out by default,
+/// but rhq manages one directory per repository.
+pub const REPOSITORY_FILE: &str = ".fossil-repo";
+
+pub fn init<P>(config: &VcsConfig, path: P) -> Result<()>
+where
+    P: AsRef<Path>,
+{
+    let path = path.as_ref();
+    fs::create_dir_all(path)?;
+    process::status(
+        config
+            .inherit()
+            .arg("init")
+            .arg(REPOSITORY_FILE)
+            .current_dir(path),
+    )?;
+    open(config, path)
+}
+
+/// Open the check-out of the repository file in `path`.
+fn open<P: AsRef<Path>>(config: &VcsConfig, path: P) -> Result<()> {
+    process::status(
+        config
+            .inherit()
+            .args(["open", "--force", REPOSITORY_FILE])
+            .current_dir(path),
+    )
+}
+
+/// Build the command to clone `url` into the repository file in `path`.
+///
+/// `args` are placed between the subcommand and the positional arguments.
+/// The directory `path` must exist before running the command.
+pub fn clone_command<P, U, I, S>(config: &VcsConfig, url: U, path: P, args: I) -> Command
+where
+    P: AsRef<Path>,
+    U: AsRef<str>,
+    I: IntoIterator<Item = S>,
+    S: AsRef<OsStr>,
+{
+    let mut command = config.inherit();
+    command
+        .arg("clone")
+        .args(args)
+        .arg(url.as_ref())
+        .arg(path.as_ref().join(REPOSITORY_FILE));
+    command
+}
+
+pub fn get_remote_url<P: AsRef<Path>>(config: &VcsConfig, path: P) -> Result<Option<String>> {
+    let output = process::output(config.piped().arg("remote-url").current_dir(path))?;
+    if !output.status.success() {
+        return Ok(None);
+    }
+    let url = String::from_utf8_lossy(&output.stdout).trim().to_owned();
+    match url.as_str() {
+        "" | "off" => Ok(None),
+        _ => Ok(Some(url)),
+    }
+}
+
+pub fn set_remote_url<P: AsRef<Path>>(config: &VcsConfig, path: P, url: &str) -> Result<()> {
+    process::status(config.piped().args(["remote-url", url]).current_dir(path))
+}
+
+pub fn status<P: AsRef<Path>>(config: &VcsConfig, path: P) -> Result<Status> {
+    // `fossil changes` does not list unmanaged files.
+    let mut changed = 0;
+    for subcommand in &["changes", "extras"] {
+        let output = process::output(config.piped().arg(subcommand).current_dir(&path))?;
+        if !output.status.success() {
+            return Err(Error::VcsFailed {
+                command: config.command.clone(),
+                code: output.status.code(),
+            });
+        }
+        changed += String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .filter(|line| !line.is_empty())
+            .count();
+    }
+    // Commits are usually pushed by autosync, but it cannot be checked without an access to the remote.
+    Ok(Status {
+        changed,
+        ahead: None,
+    })
+}
+
+pub fn fetch<P: AsRef<Path>>(config: &VcsConfig, path: P) -> Result<()> {
+    process::status(config.inherit().arg("pull").current_dir(path))
+}
+
+/// The backend of Fossil.
+pub struct FossilBackend;
+
+impl VcsBackend for FossilBackend {
+    fn vcs(&self) -> Vcs {
+        Vcs::Fossil
+    }
+
+    /// Matches the messages of `fossil clone` in the C locale.
+    fn remote_not_found(&self, message: &str) -> bool {
+        lazy_static! {
+            static ref RE_NOT_FOUND: Regex = Regex::new(r"(?m)\b404 Not Found\b").unwrap();
+        }
+        RE_NOT_FOUND.is_match(message)
+    }
+
+    fn detect(&self, path: &Path) -> bool {
+        // `_FOSSIL_` is used on Windows.
+        path.join(".fslckout").exists() || path.join("_FOSSIL_").exists()
+    }
+
+    fn init(&self, config: &VcsConfig, path: &Path) -> Result<()> {
+        init(config, path)
+    }
+
+    fn clone_command(
+        &self,
+        config: &VcsConfig,
+        url: &str,
+        path: &Path,
+        args: &[OsString],
+    ) -> Command {
+        clone_command(config, url, path, args)
+    }
+
+    fn clone_repository(
+        &self,
+        config: &VcsConfig,
+        url: &str,
+        path: &Path,
+        args: &[OsString],
+    ) -> Result<()> {
+        let created = !path.exists();
+        fs::create_dir_all(path)?;
+        let mut command = clone_command(config, url, path, args);
+        let result = super::run_clone(config, &mut command, url, |message| {
+            self.remote_not_found(message)
+        });
+        if result.is_err() && created {
+            let _ = fs::remove_dir_all(path);
+        }
+        result?;
+        open(config, path)
+    }
+
+    fn get_remote_url(&self, config: &VcsConfig, path: &Path) -> Result<Option<String>> {
+        get_remote_url(config, path)
+    }
+
+    fn set_remote_url(&self, config: &VcsConfig, path: &Path, url: &str) -> Result<()> {
+        set_remote_url(config, path, url)
+    }
+
+    fn status(&self, config: &VcsConfig, path: &Path) -> Result<Status> {
+        status(config, path)
+    }
+
+    fn fetch(&self, config: &VcsConfig, path: &Path) -> Result<()> {
+        fetch(config, path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vcs::detect_from_path;
+
+    #[test]
+    #[ignore = "requires Fossil"]
+    fn clone_local_repository() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = VcsConfig::new(Vcs::Fossil);
+
+        let seed = dir.path().join("seed");
+        FossilBackend.init(&config, &seed).unwrap();
+        assert_eq!(detect_from_path(&seed), Some(Vcs::Fossil));
+
+        let url = format!("file://{}", seed.join(REPOSITORY_FILE).display());
+        let work = dir.path().join("work");
+        FossilBackend
+            .clone_repository(&config, &url, &work, &[])
+            .unwrap();
+        assert_eq!(detect_from_path(&work), Some(Vcs::Fossil));
+        assert!(FossilBackend
+            .get_remote_url(&config, &work)
+            .unwrap()
+            .is_some());
+    }
+}
@@ -1,96 +1,742 @@
-use crate::util::process;
-use anyhow::{anyhow, Result};
-use std::{ffi::OsStr, path::Path};
-
-pub fn init<P: AsRef<Path>>(path: P) -> Result<()> {
-    process::inherit("git")
-        .arg("init")
-        .arg(path.as_ref().as_os_str())
-        .status()
-        .map_err(Into::into)
-        .and_then(|st| match st.code() {
-            Some(0) => Ok(()),
-            st => Err(anyhow!(
-                "command 'git' is exited with return code {:?}.",
-                st
-            )),
-        })
+use super::{CloneOptions, Status, Vcs, VcsBackend, VcsConfig, Version};
+use crate::error::{Error, Result};
+use crate::repository::Link;
+use crate::util::{self, process};
+use chrono::{DateTime, Local};
+use lazy_static::lazy_static;
+use regex::Regex;
+use std::{
+    ffi::{OsStr, OsString},
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+/// Returns whether `path` is a bare repository, which has no working directory (e.g. cloned with `--bare`).
+pub fn is_bare(path: &Path) -> bool {
+    // The `.git` directory of a working tree has the same layout.
+    path.file_name() != Some(OsStr::new(".git"))
+        && path.join("HEAD").is_file()
+        && path.join("objects").is_dir()
+        && path.join("refs").is_dir()
 }
 
-pub fn clone<P, U, I, S>(url: U, path: P, args: I) -> Result<()>
+pub fn init<P: AsRef<Path>>(config: &VcsConfig, path: P) -> Result<()> {
+    process::status(config.inherit().arg("init").arg(path.as_ref().as_os_str()))
+}
+
+/// Build the command to clone `url` into `path`.
+///
+/// `args` are placed between the subcommand and the positional arguments.
+pub fn clone_command<P, U, I, S>(config: &VcsConfig, url: U, path: P, args: I) -> Command
 where
     P: AsRef<Path>,
     U: AsRef<str>,
     I: IntoIterator<Item = S>,
     S: AsRef<OsStr>,
 {
-    let path = format!("{}", path.as_ref().display());
-    process::inherit("git")
+    let mut command = config.inherit();
+    command
         .arg("clone")
-        .args(&[url.as_ref(), &path])
         .args(args)
-        .status()
-        .map_err(Into::into)
-        .and_then(|st| match st.code() {
-            Some(0) => Ok(()),
-            st => Err(anyhow!(
-                "command 'git' is exited with return code {:?}.",
-                st
-            )),
-        })
+        .arg(url.as_ref())
+        .arg(path.as_ref());
+    command
+}
+
+/// Check whether `url` is a Git repository, without prompting for credentials or passphrases.
+pub fn ls_remote(config: &VcsConfig, url: &str) -> Result<bool> {
+    let mut command = config.piped();
+    command
+        .env("GIT_TERMINAL_PROMPT", "0")
+        .env("GIT_SSH_COMMAND", batch_ssh_command(config))
+        .args(["ls-remote", "--quiet", url, "HEAD"]);
+    Ok(process::output(&mut command)?.status.success())
+}
+
+/// Returns the SSH command which never prompts, based on `GIT_SSH_COMMAND` if it is set.
+fn batch_ssh_command(config: &VcsConfig) -> String {
+    let ssh = config
+        .env
+        .get("GIT_SSH_COMMAND")
+        .cloned()
+        .or_else(|| std::env::var("GIT_SSH_COMMAND").ok())
+        .filter(|command| !command.trim().is_empty())
+        .unwrap_or_else(|| "ssh".to_owned());
+    format!("{} -o BatchMode=yes", ssh)
+}
+
+/// Returns the URL of remote which the current branch tracks.
+///
+/// If the branch has no upstream, the URL of `origin` or the first remote is returned instead.
+pub fn get_remote_url<P: AsRef<Path>>(config: &VcsConfig, repo_path: P) -> Result<Option<String>> {
+    let name = match get_remote_name(config, &repo_path)? {
+        Some(name) => name,
+        None => return Ok(None),
+    };
+    let output = process::output(
+        config
+            .piped()
+            .current_dir(repo_path)
+            .args(["remote", "get-url", &name]),
+    )?;
+    if !output.status.success() {
+        return Ok(None);
+    }
+    let url = String::from_utf8_lossy(&output.stdout).trim().to_owned();
+    if url.is_empty() {
+        Ok(None)
+    } else {
+        Ok(Some(url))
+    }
 }
 
-pub fn get_remote_url<P: AsRef<Path>>(repo_path: P) -> Result<Option<String>> {
-    // 1. get current branch name.
-    let output = process::piped("git")
-        .current_dir(&repo_path)
-        .args(&["rev-parse", "--abbrev-ref", "HEAD"])
-        .output()?;
+/// Returns the name of remote which the current branch tracks,
+/// or `origin` (or the first remote if it does not exist) if there is no upstream.
+fn get_remote_name<P: AsRef<Path>>(config: &VcsConfig, repo_path: P) -> Result<Option<String>> {
+    if let Some(upstream) = get_upstream_remote(config, &repo_path)? {
+        return Ok(Some(upstream));
+    }
+    let output = process::output(config.piped().current_dir(&repo_path).arg("remote"))?;
     if !output.status.success() {
-        return Err(anyhow!("failed to get branch name"));
+        return Err(Error::VcsFailed {
+            command: config.command.clone(),
+            code: output.status.code(),
+        });
+    }
+    let remotes = String::from_utf8_lossy(&output.stdout);
+    let name = remotes
+        .lines()
+        .find(|&name| name == "origin")
+        .or_else(|| remotes.lines().next());
+    Ok(name.map(ToOwned::to_owned))
+}
+
+/// Returns the name of remote which the current branch tracks.
+///
+/// `None` is returned if HEAD is detached, or the branch has no upstream.
+fn get_upstream_remote<P: AsRef<Path>>(config: &VcsConfig, repo_path: P) -> Result<Option<String>> {
+    // The branch may have no commits yet.
+    let output = process::output(config.piped().current_dir(&repo_path).args([
+        "symbolic-ref",
+        "--quiet",
+        "--short",
+        "HEAD",
+    ]))?;
+    if !output.status.success() {
+        return Ok(None);
     }
     let branch = String::from_utf8_lossy(&output.stdout).trim().to_owned();
 
-    // 2. get remote name of upstream ref
-    let arg = format!("{}@{{upstream}}", branch);
-    let output = process::piped("git")
-        .current_dir(&repo_path)
-        .args(&["rev-parse", "--abbrev-ref", &arg])
-        .output()?;
+    let key = format!("branch.{}.remote", branch);
+    let output = process::output(
+        config
+            .piped()
+            .current_dir(&repo_path)
+            .args(["config", "--get", &key]),
+    )?;
+    let upstream = String::from_utf8_lossy(&output.stdout).trim().to_owned();
+    // `.` means that the upstream is a local branch.
+    if !output.status.success() || upstream.is_empty() || upstream == "." {
+        return Ok(None);
+    }
+    Ok(Some(upstream))
+}
+
+/// Set the URL of remote returned by `get_remote_url`, or `origin` if there is no remote.
+///
+/// The remote is newly added if it does not exist yet.
+pub fn set_remote<P: AsRef<Path>>(config: &VcsConfig, path: P, url: &str) -> Result<()> {
+    let name = get_remote_name(config, &path)
+        .ok()
+        .and_then(|name| name)
+        .unwrap_or_else(|| "origin".to_owned());
+
+    let exists = process::output(
+        config
+            .piped()
+            .args(["remote", "get-url", &name])
+            .current_dir(&path),
+    )?
+    .status
+    .success();
+    let subcommand = if exists { "set-url" } else { "add" };
+
+    process::status(
+        config
+            .piped()
+            .args(["remote", subcommand, &name, url])
+            .current_dir(path),
+    )
+}
+
+pub fn status<P: AsRef<Path>>(config: &VcsConfig, path: P) -> Result<Status> {
+    let output = process::output(
+        config
+            .piped()
+            // Untracked files are listed even if `status.showUntrackedFiles` is `no`.
+            .args(["status", "--porcelain", "--branch", "--untracked-files=all"])
+            .current_dir(path),
+    )?;
+    if !output.status.success() {
+        return Err(Error::VcsFailed {
+            command: config.command.clone(),
+            code: output.status.code(),
+        });
+    }
+    Ok(parse_status(&String::from_utf8_lossy(&output.stdout)))
+}
+
+/// Parse the output of `git status --porcelain --branch`.
+fn parse_status(output: &str) -> Status {
+    let mut lines = output.lines();
+    let ahead = lines
+        .next()
+        .filter(|header| header.starts_with("## ") && header.contains("..."))
+        .map(|header| {
+            header
+                .split("[ahead ")
+                .nth(1)
+                .and_then(|s| s.split([']', ',']).next())
+                .and_then(|n| n.parse().ok())
+                .unwrap_or(0)
+        });
+    Status {
+        changed: lines.filter(|line| !line.is_empty()).count(),
+        ahead,
+    }
+}
+
+/// Returns the number of stashes, and local branches which have unpushed commits or no upstream.
+pub fn local_only_refs<P: AsRef<Path>>(config: &VcsConfig, path: P) -> Result<usize> {
+    let mut count = 0;
+    for args in &[
+        &[
+            "for-each-ref",
+            "--format=%(upstream)|%(upstream:track)",
+            "refs/heads",
+        ][..],
+        &["stash", "list"][..],
+    ] {
+        let output = process::output(config.piped().current_dir(&path).args(*args))?;
+        if !output.status.success() {
+            return Err(Error::VcsFailed {
+                command: config.command.clone(),
+                code: output.status.code(),
+            });
+        }
+        let output = String::from_utf8_lossy(&output.stdout);
+        count += match args[0] {
+            "stash" => output.lines().count(),
+            _ => count_unpushed_branches(&output),
+        };
+    }
+    Ok(count)
+}
+
+/// Returns the number of ignored files and directories, reported by `git status --ignored`.
+pub fn ignored_files<P: AsRef<Path>>(config: &VcsConfig, path: P) -> Result<usize> {
+    let output = process::output(
+        config
+            .piped()
+            .args([
+                "status",
+                "--porcelain",
+                "--ignored",
+                "--untracked-files=normal",
+            ])
+            .current_dir(path),
+    )?;
+    if !output.status.success() {
+        return Err(Error::VcsFailed {
+            command: config.command.clone(),
+            code: output.status.code(),
+        });
+    }
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter(|line| line.starts_with("!! "))
+        .count())
+}
+
+/// Returns the paths of linked worktrees, reported by `git worktree list`.
+pub fn linked_worktrees<P: AsRef<Path>>(config: &VcsConfig, path: P) -> Result<Vec<PathBuf>> {
+    let output = process::output(
+        config
+            .piped()
+            .args(["worktree", "list", "--porcelain"])
+            .current_dir(path),
+    )?;
+    if !output.status.success() {
+        return Err(Error::VcsFailed {
+            command: config.command.clone(),
+            code: output.status.code(),
+        });
+    }
+    Ok(parse_worktree_list(&String::from_utf8_lossy(
+        &output.stdout,
+    )))
+}
+
+/// Parse the output of `git worktree list --porcelain`.
+///
+/// The first entry is the main working tree (or the bare repository), which is skipped.
+fn parse_worktree_list(output: &str) -> Vec<PathBuf> {
+    output
+        .lines()
+        .filter_map(|line| line.strip_prefix("worktree "))
+        .skip(1)
+        .map(PathBuf::from)
+        .collect()
+}
+
+/// Count the branches in the output of `git for-each-ref --format='%(upstream)|%(upstream:track)'`,
+/// which have no upstream, are ahead of it, or whose upstream is removed.
+fn count_unpushed_branches(output: &str) -> usize {
+    output
+        .lines()
+        .filter(|line| match line.split_once('|') {
+            Some((upstream, track)) => {
+                upstream.is_empty() || track.contains("ahead") || track.contains("gone")
+            }
+            None => true,
+        })
+        .count()
+}
+
+/// Returns the committer date of HEAD, or `None` if the current branch has no commits yet.
+pub fn last_commit_time<P: AsRef<Path>>(
+    config: &VcsConfig,
+    path: P,
+) -> Result<Option<DateTime<Local>>> {
+    let output =
+        process::output(
+            config
+                .piped()
+                .current_dir(path)
+                .args(["log", "-1", "--format=%cI"]),
+        )?;
     if !output.status.success() {
         return Ok(None);
     }
-    let upstream = String::from_utf8_lossy(&output.stdout)
-        .trim()
-        .trim_end_matches(&format!("/{}", branch))
-        .to_owned();
+    let date = String::from_utf8_lossy(&output.stdout);
+    Ok(DateTime::parse_from_rfc3339(date.trim())
+        .ok()
+        .map(|date| date.with_timezone(&Local)))
+}
 
-    // 3. get remote URL of upstream ref
-    let output = process::piped("git")
-        .current_dir(repo_path)
-        .args(&["remote", "get-url", &upstream])
-        .output()?;
+/// Returns the default branch of the remote, which `refs/remotes/<remote>/HEAD` points to.
+pub fn default_branch<P: AsRef<Path>>(config: &VcsConfig, path: P) -> Result<Option<String>> {
+    let remote = match get_remote_name(config, &path)? {
+        Some(remote) => remote,
+        None => return Ok(None),
+    };
+    let output = process::output(config.piped().current_dir(path).args([
+        "symbolic-ref",
+        "--quiet",
+        "--short",
+        &format!("refs/remotes/{}/HEAD", remote),
+    ]))?;
     if !output.status.success() {
         return Ok(None);
     }
-    let url = String::from_utf8_lossy(&output.stdout).trim().to_owned();
-    if url == "" {
-        Ok(None)
+    let head = String::from_utf8_lossy(&output.stdout);
+    let prefix = format!("{}/", remote);
+    Ok(head.trim().strip_prefix(&prefix).map(ToOwned::to_owned))
+}
+
+/// Returns the Git directory of the working tree at `path`.
+///
+/// `.git` is either the Git directory itself, or a file pointing to it (e.g. in linked worktrees and submodules).
+pub fn git_dir(path: &Path) -> Option<PathBuf> {
+    let dot_git = path.join(".git");
+    if fs::metadata(&dot_git).ok()?.is_dir() {
+        return Some(dot_git);
+    }
+    let content = fs::read_to_string(&dot_git).ok()?;
+    let dir = content.lines().next()?.strip_prefix("gitdir:")?.trim();
+    Some(path.join(dir))
+}
+
+/// Determine whether the working tree at `path` is a linked worktree or a submodule.
+pub fn link(path: &Path) -> Option<Link> {
+    if !fs::metadata(path.join(".git")).ok()?.is_file() {
+        return None;
+    }
+    let dir = util::canonicalize_pretty(git_dir(path)?).ok()?;
+
+    // A linked worktree refers to the Git directory of the main working tree by `commondir`.
+    if let Ok(common_dir) = fs::read_to_string(dir.join("commondir")) {
+        let common_dir = util::canonicalize_pretty(dir.join(common_dir.trim())).ok()?;
+        let main = match common_dir.file_name() {
+            Some(name) if name == ".git" => common_dir.parent()?.to_owned(),
+            // The main repository is bare.
+            _ => common_dir,
+        };
+        return Some(Link::Worktree { main });
+    }
+
+    // The Git directory of a submodule is placed in `modules` of the superproject.
+    let superproject = path
+        .ancestors()
+        .skip(1)
+        .find(|dir| dir.join(".git").exists())?;
+    let modules = util::canonicalize_pretty(git_dir(superproject)?.join("modules")).ok()?;
+    if dir.starts_with(modules) {
+        Some(Link::Submodule {
+            superproject: superproject.to_owned(),
+        })
     } else {
-        Ok(Some(url))
+        None
+    }
+}
+
+pub fn fetch<P: AsRef<Path>>(config: &VcsConfig, path: P) -> Result<()> {
+    process::status(config.inherit().arg("fetch").current_dir(path))
+}
+
+/// The backend of Git.
+pub struct GitBackend;
+
+impl VcsBackend for GitBackend {
+    fn vcs(&self) -> Vcs {
+        Vcs::Git
+    }
+
+    /// Matches the messages of `git clone` in the C locale.
+    fn remote_not_found(&self, message: &str) -> bool {
+        lazy_static! {
+            static ref RE_NOT_FOUND: Regex = Regex::new(r"(?m)^(?:fatal: repository '.*' not found|fatal: '.*' does not appear to be a git repository|(?:ERROR|remote): Repository not found\.?)\s*$").unwrap();
+        }
+        RE_NOT_FOUND.is_match(message)
+    }
+
+    fn detect(&self, path: &Path) -> bool {
+        path.join(".git").exists() || is_bare(path)
+    }
+
+    fn minimum_version(&self) -> Option<Version> {
+        Some(Version::new(2, 0, 0))
+    }
+
+    fn init(&self, config: &VcsConfig, path: &Path) -> Result<()> {
+        #[cfg(feature = "git2")]
+        {
+            if config.uses_library() {
+                return super::libgit2::init(path);
+            }
+        }
+        init(config, path)
+    }
+
+    fn clone_command(
+        &self,
+        config: &VcsConfig,
+        url: &str,
+        path: &Path,
+        args: &[OsString],
+    ) -> Command {
+        clone_command(config, url, path, args)
+    }
+
+    fn clone_repository(
+        &self,
+        config: &VcsConfig,
+        url: &str,
+        path: &Path,
+        args: &[OsString],
+    ) -> Result<()> {
+        // The library cannot interpret the arguments of command line.
+        #[cfg(feature = "git2")]
+        {
+            if config.uses_library() && args.is_empty() {
+                return super::libgit2::clone(url, path);
+            }
+        }
+        let mut command = clone_command(config, url, path, args);
+        super::run_clone(config, &mut command, url, |message| {
+            self.remote_not_found(message)
+        })
+    }
+
+    fn clone_args(&self, options: &CloneOptions) -> Result<Vec<String>> {
+        let mut args = vec![];
+        if let Some(depth) = options.depth {
+            args.extend(["--depth".to_owned(), depth.to_string()]);
+        }
+        if let Some(ref branch) = options.branch {
+            args.extend(["--branch".to_owned(), branch.clone()]);
+        }
+        if options.recursive {
+            args.push("--recurse-submodules".to_owned());
+        }
+        if options.bare {
+            args.push("--bare".to_owned());
+        }
+        Ok(args)
+    }
+
+    fn is_remote_repository(&self, config: &VcsConfig, url: &str) -> Result<bool> {
+        ls_remote(config, url)
+    }
+
+    fn get_remote_url(&self, config: &VcsConfig, path: &Path) -> Result<Option<String>> {
+        #[cfg(feature = "git2")]
+        {
+            if config.uses_library() {
+                return super::libgit2::get_remote_url(path);
+            }
+        }
+        get_remote_url(config, path)
+    }
+
+    fn set_remote_url(&self, config: &VcsConfig, path: &Path, url: &str) -> Result<()> {
+        #[cfg(feature = "git2")]
+        {
+            if config.uses_library() {
+                return super::libgit2::set_remote(path, url);
+            }
+        }
+        set_remote(config, path, url)
+    }
+
+    fn status(&self, config: &VcsConfig, path: &Path) -> Result<Status> {
+        #[cfg(feature = "git2")]
+        {
+            if config.uses_library() {
+                return super::libgit2::status(path);
+            }
+        }
+        status(config, path)
+    }
+
+    fn fetch(&self, config: &VcsConfig, path: &Path) -> Result<()> {
+        #[cfg(feature = "git2")]
+        {
+            if config.uses_library() {
+                return super::libgit2::fetch(path);
+            }
+        }
+        fetch(config, path)
+    }
+
+    fn last_commit_time(&self, config: &VcsConfig, path: &Path) -> Result<Option<DateTime<Local>>> {
+        #[cfg(feature = "git2")]
+        {
+            if config.uses_library() {
+                return super::libgit2::last_commit_time(path);
+            }
+        }
+        last_commit_time(config, path)
+    }
+
+    fn default_branch(&self, config: &VcsConfig, path: &Path) -> Result<Option<String>> {
+        #[cfg(feature = "git2")]
+        {
+            if config.uses_library() {
+                return super::libgit2::default_branch(path);
+            }
+        }
+        default_branch(config, path)
+    }
+
+    fn local_only_refs(&self, config: &VcsConfig, path: &Path) -> Result<usize> {
+        #[cfg(feature = "git2")]
+        {
+            if config.uses_library() {
+                return super::libgit2::local_only_refs(path);
+            }
+        }
+        local_only_refs(config, path)
+    }
+
+    fn ignored_files(&self, config: &VcsConfig, path: &Path) -> Result<usize> {
+        #[cfg(feature = "git2")]
+        {
+            if config.uses_library() {
+                return super::libgit2::ignored_files(path);
+            }
+        }
+        ignored_files(config, path)
+    }
+
+    fn linked_worktrees(&self, config: &VcsConfig, path: &Path) -> Result<Vec<PathBuf>> {
+        #[cfg(feature = "git2")]
+        {
+            if config.uses_library() {
+                return super::libgit2::linked_worktrees(path);
+            }
+        }
+        linked_worktrees(config, path)
     }
 }
 
-pub fn set_remote<P: AsRef<Path>>(path: P, url: &str) -> Result<()> {
-    let st = process::piped("git")
-        .args(&["remote", "add", "origin", url])
-        .current_dir(path)
-        .status()?;
-    match st.code() {
-        Some(0) => Ok(()),
-        st => Err(anyhow!(
-            "command 'git' is exited with return code {:?}.",
-            st
-        )),
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_status() {
+        let status = parse_status(
+            "## master...origin/master [ahead 2, behind 1]\n M src/lib.rs\n?? new.txt\n",
+        );
+        assert_eq!(status.changed, 2);
+        assert_eq!(status.ahead, Some(2));
+
+        let status = parse_status("## master...origin/master\n");
+        assert!(status.is_clean());
+
+        // no upstream
+        let status = parse_status("## master\n");
+        assert_eq!(status.ahead, None);
+    }
+
+    #[test]
+    fn test_parse_worktree_list() {
+        let output = "worktree /src/main\nHEAD 0123abcd\nbranch refs/heads/master\n\n\
+                      worktree /src/feature\nHEAD 4567cdef\ndetached\n\n\
+                      worktree /tmp/gone\nHEAD 89abcdef\nbranch refs/heads/gone\n\
+                      prunable gitdir file points to non-existent location\n";
+        assert_eq!(
+            parse_worktree_list(output),
+            [PathBuf::from("/src/feature"), PathBuf::from("/tmp/gone")]
+        );
+        assert!(parse_worktree_list("worktree /src/main\nbare\n").is_empty());
+    }
+
+    #[test]
+    fn test_count_unpushed_branches() {
+        let output = "refs/remotes/origin/master|\n\
+                      refs/remotes/origin/feature|[ahead 1, behind 2]\n\
+                      refs/remotes/origin/fix|[behind 3]\n\
+                      refs/remotes/origin/old|[gone]\n\
+                      |\n";
+        assert_eq!(count_unpushed_branches(output), 3);
+    }
+
+    fn git(dir: &Path, args: &[&str]) {
+        let status = Command::new("git")
+            .args(["-c", "user.name=rhq", "-c", "user.email=rhq@example.com"])
+            .args(["-c", "protocol.file.allow=always"])
+            .args(args)
+            .current_dir(dir)
+            .stdout(std::process::Stdio::null())
+            .stderr(std::process::Stdio::null())
+            .status()
+            .expect("git is required to run this test");
+        assert!(status.success(), "git {:?}", args);
+    }
+
+    #[test]
+    fn remote_url_without_upstream() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path();
+        let config = VcsConfig::new(Vcs::Git);
+        git(path, &["init", "-q"]);
+        assert_eq!(get_remote_url(&config, path).unwrap(), None);
+
+        git(path, &["remote", "add", "backup", "/mnt/backup/repo"]);
+        assert_eq!(
+            get_remote_url(&config, path).unwrap().as_deref(),
+            Some("/mnt/backup/repo")
+        );
+
+        git(
+            path,
+            &["remote", "add", "origin", "https://github.com/foo/bar.git"],
+        );
+        assert_eq!(
+            get_remote_url(&config, path).unwrap().as_deref(),
+            Some("https://github.com/foo/bar.git")
+        );
+        #[cfg(feature = "git2")]
+        assert_eq!(
+            super::super::libgit2::get_remote_url(path).unwrap(),
+            get_remote_url(&config, path).unwrap()
+        );
+    }
+
+    #[test]
+    fn last_commit_and_default_branch() {
+        let dir = tempfile::tempdir().unwrap();
+        let seed = dir.path().join("seed");
+        let config = VcsConfig::new(Vcs::Git);
+        fs::create_dir(&seed).unwrap();
+        git(&seed, &["init", "-q", "-b", "trunk"]);
+        assert_eq!(last_commit_time(&config, &seed).unwrap(), None);
+        git(&seed, &["commit", "-q", "--allow-empty", "-m", "init"]);
+        assert!(last_commit_time(&config, &seed).unwrap().is_some());
+
+        git(dir.path(), &["clone", "-q", "seed", "work"]);
+        let work = dir.path().join("work");
+        assert_eq!(
+            default_branch(&config, &work).unwrap().as_deref(),
+            Some("trunk")
+        );
+        #[cfg(feature = "git2")]
+        {
+            assert_eq!(
+                super::super::libgit2::default_branch(&work).unwrap(),
+                default_branch(&config, &work).unwrap()
+            );
+            assert_eq!(
+                super::super::libgit2::last_commit_time(&work).unwrap(),
+                last_commit_time(&config, &work).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn local_only_branches_and_stashes() {
+        let dir = tempfile::tempdir().unwrap();
+        let seed = dir.path().join("seed");
+        let config = VcsConfig::new(Vcs::Git);
+        fs::create_dir(&seed).unwrap();
+        git(&seed, &["init", "-q"]);
+        fs::write(seed.join("README"), "").unwrap();
+        git(&seed, &["add", "README"]);
+        git(&seed, &["commit", "-q", "-m", "init"]);
+        git(dir.path(), &["clone", "-q", "seed", "work"]);
+        let work = dir.path().join("work");
+        let count = |expected| {
+            assert_eq!(local_only_refs(&config, &work).unwrap(), expected);
+            #[cfg(feature = "git2")]
+            assert_eq!(
+                super::super::libgit2::local_only_refs(&work).unwrap(),
+                expected
+            );
+        };
+        count(0);
+
+        git(&work, &["branch", "topic"]);
+        count(1);
+        fs::write(work.join("README"), "changed").unwrap();
+        git(&work, &["stash", "-q"]);
+        count(2);
+    }
+
+    #[test]
+    fn detect_worktree_and_submodule() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = util::canonicalize_pretty(dir.path()).unwrap();
+        let main = root.join("main");
+        let lib = root.join("lib");
+        for path in [&main, &lib] {
+            fs::create_dir(path).unwrap();
+            git(path, &["init", "-q"]);
+            git(path, &["commit", "-q", "--allow-empty", "-m", "init"]);
+        }
+        git(&main, &["worktree", "add", "-q", "../feature"]);
+        git(&main, &["submodule", "add", "-q", "../lib", "vendor/lib"]);
+
+        assert_eq!(link(&main), None);
+        assert_eq!(
+            link(&root.join("feature")),
+            Some(Link::Worktree { main: main.clone() })
+        );
+        assert_eq!(
+            link(&main.join("vendor/lib")),
+            Some(Link::Submodule { superproject: main })
+        );
     }
 }
@@ -1,68 +1,300 @@
+use super::{CloneOptions, Status, Vcs, VcsBackend, VcsConfig, Version};
+use crate::error::{Error, Result};
 use crate::util::process;
-use anyhow::{anyhow, Result};
-use std::{ffi::OsStr, path::Path};
+use chrono::{DateTime, Local};
+use lazy_static::lazy_static;
+use regex::Regex;
+use std::{
+    ffi::{OsStr, OsString},
+    path::Path,
+    process::Command,
+};
 
-pub fn init<P>(path: P) -> Result<()>
+pub fn init<P>(config: &VcsConfig, path: P) -> Result<()>
 where
     P: AsRef<Path>,
 {
-    process::inherit("hg")
-        .arg("init")
-        .arg(path.as_ref().as_os_str())
-        .status()
-        .map_err(Into::into)
-        .and_then(|st| match st.code() {
-            Some(0) => Ok(()),
-            st => Err(anyhow!("command 'hg' is exited with return code {:?}.", st)),
-        })
+    process::status(config.inherit().arg("init").arg(path.as_ref().as_os_str()))
 }
 
-pub fn clone<P, U, I, S>(url: U, path: P, args: I) -> Result<()>
+/// Build the command to clone `url` into `path`.
+///
+/// `args` are placed between the subcommand and the positional arguments.
+pub fn clone_command<P, U, I, S>(config: &VcsConfig, url: U, path: P, args: I) -> Command
 where
     P: AsRef<Path>,
     U: AsRef<str>,
     I: IntoIterator<Item = S>,
     S: AsRef<OsStr>,
 {
-    let path = format!("{}", path.as_ref().display());
-    process::inherit("hg")
+    let mut command = config.inherit();
+    command
         .arg("clone")
         .args(args)
-        .args(&[url.as_ref(), &path])
-        .status()
-        .map_err(Into::into)
-        .and_then(|st| match st.code() {
-            Some(0) => Ok(()),
-            st => Err(anyhow!("command 'hg' is exited with return code {:?}.", st)),
-        })
+        .arg(url.as_ref())
+        .arg(path.as_ref());
+    command
+}
+
+/// Check whether `url` is a Mercurial repository, without prompting for credentials or passphrases.
+pub fn identify(config: &VcsConfig, url: &str) -> Result<bool> {
+    let output = process::output(config.piped().args([
+        "identify",
+        "--noninteractive",
+        "--config",
+        "ui.ssh=ssh -o BatchMode=yes",
+        url,
+    ]))?;
+    Ok(output.status.success())
 }
 
-pub fn get_remote_url<P: AsRef<Path>>(repo_path: P) -> Result<Option<String>> {
+pub fn get_remote_url<P: AsRef<Path>>(config: &VcsConfig, repo_path: P) -> Result<Option<String>> {
     // 1. get current branch
-    let output = process::piped("hg")
-        .arg("branch")
-        .current_dir(&repo_path)
-        .output()?;
+    let output = process::output(config.piped().arg("branch").current_dir(&repo_path))?;
     if !output.status.success() {
-        return Err(anyhow!("hg: failed to get branch name"));
+        return Err(Error::VcsFailed {
+            command: config.command.clone(),
+            code: output.status.code(),
+        });
     }
     let branch = String::from_utf8_lossy(&output.stdout)
         .trim_end()
         .to_owned();
 
     // 2. get URL
-    let output = process::piped("hg")
-        .arg("paths")
-        .arg(branch)
-        .current_dir(repo_path)
-        .output()?;
+    let output = process::output(
+        config
+            .piped()
+            .arg("paths")
+            .arg(branch)
+            .current_dir(&repo_path),
+    )?;
+    let url = String::from_utf8_lossy(&output.stdout).trim().to_owned();
+    if output.status.success() && !url.is_empty() {
+        return Ok(Some(url));
+    }
+
+    // 3. fall back to `default` or the first path
+    let output = process::output(config.piped().arg("paths").current_dir(repo_path))?;
     if !output.status.success() {
         return Ok(None);
     }
-    let url = String::from_utf8_lossy(&output.stdout).trim().to_owned();
-    if url == "" {
-        Ok(None)
-    } else {
-        Ok(Some(url))
+    let paths = parse_paths(&String::from_utf8_lossy(&output.stdout));
+    let url = paths
+        .iter()
+        .find(|(name, _)| name == "default")
+        .or_else(|| paths.first())
+        .map(|(_, url)| url.clone());
+    Ok(url)
+}
+
+/// Parse the output of `hg paths` into pairs of the name and URL.
+fn parse_paths(output: &str) -> Vec<(String, String)> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let (name, url) = line.split_once(" = ")?;
+            Some((name.trim().to_owned(), url.trim().to_owned()))
+        })
+        .collect()
+}
+
+/// Returns the date of the parent of the working directory, or `None` if there are no commits yet.
+pub fn last_commit_time<P: AsRef<Path>>(
+    config: &VcsConfig,
+    path: P,
+) -> Result<Option<DateTime<Local>>> {
+    let output = process::output(
+        config
+            .piped()
+            .args(["log", "-r", ".", "-T", "{date|rfc3339date}"])
+            .current_dir(path),
+    )?;
+    if !output.status.success() {
+        return Ok(None);
+    }
+    let date = String::from_utf8_lossy(&output.stdout);
+    Ok(DateTime::parse_from_rfc3339(date.trim())
+        .ok()
+        .map(|date| date.with_timezone(&Local)))
+}
+
+pub fn status<P: AsRef<Path>>(config: &VcsConfig, path: P) -> Result<Status> {
+    let output = process::output(config.piped().arg("status").current_dir(&path))?;
+    if !output.status.success() {
+        return Err(Error::VcsFailed {
+            command: config.command.clone(),
+            code: output.status.code(),
+        });
+    }
+    let changed = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter(|line| !line.is_empty())
+        .count();
+    Ok(Status {
+        changed,
+        ahead: outgoing(config, path)?,
+    })
+}
+
+/// Returns the number of changesets not pushed to the default path, reported by `hg outgoing`.
+///
+/// This requires an access to the remote; `None` is returned if it is not configured or unreachable.
+fn outgoing<P: AsRef<Path>>(config: &VcsConfig, path: P) -> Result<Option<usize>> {
+    let output = process::output(
+        config
+            .piped()
+            .args([
+                "outgoing",
+                "--quiet",
+                "--noninteractive",
+                "--config",
+                "ui.ssh=ssh -o BatchMode=yes",
+            ])
+            .current_dir(path),
+    )?;
+    // `hg outgoing` exits with 1 if there are no outgoing changesets.
+    Ok(match output.status.code() {
+        Some(0) => Some(
+            String::from_utf8_lossy(&output.stdout)
+                .lines()
+                .filter(|line| !line.is_empty())
+                .count(),
+        ),
+        Some(1) => Some(0),
+        _ => None,
+    })
+}
+
+/// Returns the number of ignored files, reported by `hg status --ignored`.
+pub fn ignored_files<P: AsRef<Path>>(config: &VcsConfig, path: P) -> Result<usize> {
+    let output = process::output(
+        config
+            .piped()
+            .args(["status", "--ignored"])
+            .current_dir(path),
+    )?;
+    if !output.status.success() {
+        return Err(Error::VcsFailed {
+            command: config.command.clone(),
+            code: output.status.code(),
+        });
+    }
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter(|line| !line.is_empty())
+        .count())
+}
+
+pub fn fetch<P: AsRef<Path>>(config: &VcsConfig, path: P) -> Result<()> {
+    process::status(config.inherit().arg("pull").current_dir(path))
+}
+
+/// The backend of Mercurial.
+pub struct HgBackend;
+
+impl VcsBackend for HgBackend {
+    fn vcs(&self) -> Vcs {
+        Vcs::Hg
+    }
+
+    /// Matches the messages of `hg clone` in the C locale.
+    fn remote_not_found(&self, message: &str) -> bool {
+        lazy_static! {
+            static ref RE_NOT_FOUND: Regex = Regex::new(
+                r"(?m)^(?:remote: )?abort: (?:repository .* not found!?|HTTP Error 404: .*)\s*$"
+            )
+            .unwrap();
+        }
+        RE_NOT_FOUND.is_match(message)
+    }
+
+    fn detect(&self, path: &Path) -> bool {
+        path.join(".hg").exists()
+    }
+
+    fn minimum_version(&self) -> Option<Version> {
+        Some(Version::new(3, 0, 0))
+    }
+
+    fn init(&self, config: &VcsConfig, path: &Path) -> Result<()> {
+        init(config, path)
+    }
+
+    fn clone_command(
+        &self,
+        config: &VcsConfig,
+        url: &str,
+        path: &Path,
+        args: &[OsString],
+    ) -> Command {
+        clone_command(config, url, path, args)
+    }
+
+    /// Subrepositories are cloned by default, so `recursive` is ignored.
+    fn clone_args(&self, options: &CloneOptions) -> Result<Vec<String>> {
+        if options.depth.is_some() {
+            return Err(Error::Unsupported {
+                vcs: Vcs::Hg,
+                operation: "cloning with --depth",
+            });
+        }
+        let mut args = vec![];
+        if let Some(ref branch) = options.branch {
+            args.extend(["--branch".to_owned(), branch.clone()]);
+        }
+        if options.bare {
+            args.push("--noupdate".to_owned());
+        }
+        Ok(args)
+    }
+
+    fn is_remote_repository(&self, config: &VcsConfig, url: &str) -> Result<bool> {
+        identify(config, url)
+    }
+
+    fn get_remote_url(&self, config: &VcsConfig, path: &Path) -> Result<Option<String>> {
+        get_remote_url(config, path)
+    }
+
+    fn status(&self, config: &VcsConfig, path: &Path) -> Result<Status> {
+        status(config, path)
+    }
+
+    fn ignored_files(&self, config: &VcsConfig, path: &Path) -> Result<usize> {
+        ignored_files(config, path)
+    }
+
+    fn fetch(&self, config: &VcsConfig, path: &Path) -> Result<()> {
+        fetch(config, path)
+    }
+
+    fn last_commit_time(&self, config: &VcsConfig, path: &Path) -> Result<Option<DateTime<Local>>> {
+        last_commit_time(config, path)
+    }
+
+    fn default_branch(&self, _config: &VcsConfig, _path: &Path) -> Result<Option<String>> {
+        // Mercurial has no notion of the default branch of remotes, and `default` is used conventionally.
+        Ok(Some("default".to_owned()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_paths() {
+        let output = "backup = /mnt/backup/repo\ndefault = https://example.com/foo/bar\n";
+        assert_eq!(
+            parse_paths(output),
+            [
+                ("backup".to_owned(), "/mnt/backup/repo".to_owned()),
+                (
+                    "default".to_owned(),
+                    "https://example.com/foo/bar".to_owned()
+                ),
+            ]
+        );
     }
 }
@@ -0,0 +1,260 @@
+use super::{Status, Vcs, VcsBackend, VcsConfig, Version};
+use crate::error::{Error, Result};
+use crate::util::process;
+use lazy_static::lazy_static;
+use regex::Regex;
+use std::{
+    ffi::{OsStr, OsString},
+    path::Path,
+    process::{Command, Output},
+};
+
+pub fn init<P>(config: &VcsConfig, path: P) -> Result<()>
+where
+    P: AsRef<Path>,
+{
+    process::status(
+        config
+            .inherit()
+            .args(["git", "init"])
+            .arg(path.as_ref().as_os_str()),
+    )
+}
+
+/// Build the command to clone `url` into `path`.
+///
+/// `args` are placed between the subcommand and the positional arguments.
+pub fn clone_command<P, U, I, S>(config: &VcsConfig, url: U, path: P, args: I) -> Command
+where
+    P: AsRef<Path>,
+    U: AsRef<str>,
+    I: IntoIterator<Item = S>,
+    S: AsRef<OsStr>,
+{
+    let mut command = config.inherit();
+    command
+        .args(["git", "clone"])
+        .args(args)
+        .arg(url.as_ref())
+        .arg(path.as_ref());
+    command
+}
+
+fn output<P, I, S>(config: &VcsConfig, path: P, args: I) -> Result<Output>
+where
+    P: AsRef<Path>,
+    I: IntoIterator<Item = S>,
+    S: AsRef<OsStr>,
+{
+    let output = process::output(
+        config
+            .piped()
+            .args(["--no-pager", "--color=never"])
+            .args(args)
+            .current_dir(path),
+    )?;
+    if !output.status.success() {
+        return Err(Error::VcsFailed {
+            command: config.command.clone(),
+            code: output.status.code(),
+        });
+    }
+    Ok(output)
+}
+
+/// Returns the URL of `origin`, or the first remote if it does not exist.
+pub fn get_remote_url<P: AsRef<Path>>(config: &VcsConfig, path: P) -> Result<Option<String>> {
+    let output = output(config, path, ["git", "remote", "list"])?;
+    let remotes = parse_remote_list(&String::from_utf8_lossy(&output.stdout));
+    Ok(find_remote(&remotes).map(|(_, url)| url.clone()))
+}
+
+/// Returns `origin`, or the first remote if it does not exist, as Git does when there is no upstream.
+fn find_remote(remotes: &[(String, String)]) -> Option<&(String, String)> {
+    remotes
+        .iter()
+        .find(|(name, _)| name == "origin")
+        .or_else(|| remotes.first())
+}
+
+/// Parse the output of `jj git remote list` into pairs of the name and URL.
+fn parse_remote_list(output: &str) -> Vec<(String, String)> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let mut words = line.split_whitespace();
+            Some((words.next()?.to_owned(), words.next()?.to_owned()))
+        })
+        .collect()
+}
+
+/// Set the URL of remote returned by `get_remote_url`, or `origin` if there is no remote.
+///
+/// The remote is newly added if it does not exist yet.
+pub fn set_remote<P: AsRef<Path>>(config: &VcsConfig, path: P, url: &str) -> Result<()> {
+    let output = output(config, &path, ["git", "remote", "list"])?;
+    let remotes = parse_remote_list(&String::from_utf8_lossy(&output.stdout));
+    let (subcommand, name) = match find_remote(&remotes) {
+        Some((name, _)) => ("set-url", name.as_str()),
+        None => ("add", "origin"),
+    };
+    process::status(
+        config
+            .piped()
+            .args(["git", "remote", subcommand, name, url])
+            .current_dir(path),
+    )
+}
+
+/// Returns the summary of changes in the working-copy commit,
+/// and the number of non-empty commits which are not on any remote bookmark.
+pub fn status<P: AsRef<Path>>(config: &VcsConfig, path: P) -> Result<Status> {
+    let changed = output(config, &path, ["diff", "--summary"])?
+        .stdout
+        .split(|&b| b == b'\n')
+        .filter(|line| !line.is_empty())
+        .count();
+
+    let remote_bookmarks = output(
+        config,
+        &path,
+        [
+            "log",
+            "--no-graph",
+            "-r",
+            "remote_bookmarks()",
+            "-T",
+            "\"x\"",
+        ],
+    )?;
+    let ahead = if remote_bookmarks.stdout.is_empty() {
+        None
+    } else {
+        let commits = output(
+            config,
+            &path,
+            [
+                "log",
+                "--no-graph",
+                "-r",
+                "remote_bookmarks()..@- ~ empty()",
+                "-T",
+                "\"x\"",
+            ],
+        )?;
+        Some(commits.stdout.len())
+    };
+
+    Ok(Status { changed, ahead })
+}
+
+pub fn fetch<P: AsRef<Path>>(config: &VcsConfig, path: P) -> Result<()> {
+    process::status(config.inherit().args(["git", "fetch"]).current_dir(path))
+}
+
+/// The backend of Jujutsu.
+///
+/// A colocated repository has both `.jj` and `.git`, and is handled by this backend.
+pub struct JjBackend;
+
+impl VcsBackend for JjBackend {
+    fn vcs(&self) -> Vcs {
+        Vcs::Jujutsu
+    }
+
+    /// Matches the messages of `jj git clone` (and those of Git forwarded by it) in the C locale.
+    fn remote_not_found(&self, message: &str) -> bool {
+        lazy_static! {
+            static ref RE_NOT_FOUND: Regex =
+                Regex::new(r"(?m)^Error: .*(?:could not find repository|[Rr]epository not found)")
+                    .unwrap();
+        }
+        RE_NOT_FOUND.is_match(message)
+            // the messages of Git are forwarded when it is used as the subprocess.
+            || super::git::GitBackend.remote_not_found(message)
+    }
+
+    fn detect(&self, path: &Path) -> bool {
+        path.join(".jj").is_dir()
+    }
+
+    fn minimum_version(&self) -> Option<Version> {
+        // The revset `remote_bookmarks()` was introduced in 0.22.
+        Some(Version::new(0, 22, 0))
+    }
+
+    fn init(&self, config: &VcsConfig, path: &Path) -> Result<()> {
+        init(config, path)
+    }
+
+    fn clone_command(
+        &self,
+        config: &VcsConfig,
+        url: &str,
+        path: &Path,
+        args: &[OsString],
+    ) -> Command {
+        clone_command(config, url, path, args)
+    }
+
+    fn get_remote_url(&self, config: &VcsConfig, path: &Path) -> Result<Option<String>> {
+        get_remote_url(config, path)
+    }
+
+    fn set_remote_url(&self, config: &VcsConfig, path: &Path, url: &str) -> Result<()> {
+        set_remote(config, path, url)
+    }
+
+    fn status(&self, config: &VcsConfig, path: &Path) -> Result<Status> {
+        status(config, path)
+    }
+
+    fn fetch(&self, config: &VcsConfig, path: &Path) -> Result<()> {
+        fetch(config, path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vcs::detect_from_path;
+    use std::fs;
+
+    #[test]
+    fn test_parse_remote_list() {
+        let output = "backup /mnt/backup/repo\norigin https://github.com/foo/bar.git\n";
+        assert_eq!(
+            parse_remote_list(output),
+            [
+                ("backup".to_owned(), "/mnt/backup/repo".to_owned()),
+                (
+                    "origin".to_owned(),
+                    "https://github.com/foo/bar.git".to_owned()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_find_remote() {
+        let remote = |name: &str| (name.to_owned(), format!("https://example.com/{}", name));
+        assert_eq!(find_remote(&[]), None);
+        assert_eq!(
+            find_remote(&[remote("upstream"), remote("origin")]),
+            Some(&remote("origin"))
+        );
+        assert_eq!(
+            find_remote(&[remote("upstream"), remote("backup")]),
+            Some(&remote("upstream"))
+        );
+    }
+
+    #[test]
+    fn detect_colocated_repository() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join(".git")).unwrap();
+        assert_eq!(detect_from_path(dir.path()), Some(Vcs::Git));
+        fs::create_dir(dir.path().join(".jj")).unwrap();
+        assert_eq!(detect_from_path(dir.path()), Some(Vcs::Jujutsu));
+    }
+}
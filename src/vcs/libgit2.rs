@@ -0,0 +1,376 @@
+//! Implementation of Git operations by using libgit2, instead of spawning `git`.
+
+use super::{git::GitBackend, Status, VcsBackend};
+use crate::error::{Error, Result};
+use chrono::{DateTime, Local, TimeZone};
+use git2::{
+    build::RepoBuilder, Branch, BranchType, Cred, CredentialType, ErrorCode, FetchOptions,
+    RemoteCallbacks, Repository, StatusOptions,
+};
+use std::path::{Path, PathBuf};
+
+pub fn init<P: AsRef<Path>>(path: P) -> Result<()> {
+    Repository::init(path)?;
+    Ok(())
+}
+
+pub fn clone<P: AsRef<Path>>(url: &str, path: P) -> Result<()> {
+    let mut options = FetchOptions::new();
+    options.remote_callbacks(callbacks());
+    match RepoBuilder::new()
+        .fetch_options(options)
+        .clone(url, path.as_ref())
+    {
+        Ok(..) => Ok(()),
+        Err(err) if is_not_found(&err) => Err(Error::RemoteNotFound {
+            url: url.to_owned(),
+        }),
+        Err(err) => Err(err.into()),
+    }
+}
+
+fn is_not_found(err: &git2::Error) -> bool {
+    err.code() == ErrorCode::NotFound
+        || GitBackend.remote_not_found(err.message())
+        || err.message().contains("status code: 404")
+        // reported by the local transport
+        || err.message().starts_with("failed to resolve path")
+}
+
+pub fn get_remote_url<P: AsRef<Path>>(path: P) -> Result<Option<String>> {
+    let repo = Repository::open(path)?;
+    let name = match get_remote_name(&repo)? {
+        Some(name) => name,
+        None => return Ok(None),
+    };
+    let url = match repo.find_remote(&name) {
+        Ok(remote) => remote.url().ok().map(ToOwned::to_owned),
+        Err(..) => None,
+    };
+    Ok(url.filter(|url| !url.is_empty()))
+}
+
+/// Returns the name of remote which the current branch tracks,
+/// or `origin` (or the first remote if it does not exist) if there is no upstream.
+fn get_remote_name(repo: &Repository) -> Result<Option<String>> {
+    if let Some(upstream) = get_upstream_remote(repo)? {
+        return Ok(Some(upstream));
+    }
+    let remotes = repo.remotes()?;
+    let names: Vec<&str> = remotes
+        .iter()
+        .filter_map(|name| name.ok().flatten())
+        .collect();
+    let name = names
+        .iter()
+        .find(|&&name| name == "origin")
+        .or_else(|| names.first());
+    Ok(name.map(|&name| name.to_owned()))
+}
+
+/// Returns the name of remote which the current branch tracks.
+fn get_upstream_remote(repo: &Repository) -> Result<Option<String>> {
+    // `repo.head()` fails if the branch has no commits yet.
+    let head = repo.find_reference("HEAD")?;
+    let name = match head.symbolic_target()? {
+        Some(name) => name,
+        None => return Ok(None),
+    };
+    match repo.branch_upstream_remote(name) {
+        Ok(remote) => Ok(remote
+            .as_str()
+            .ok()
+            .filter(|&name| name != ".")
+            .map(ToOwned::to_owned)),
+        Err(..) => Ok(None),
+    }
+}
+
+/// Set the URL of remote returned by `get_remote_url`, or `origin` if there is no remote.
+///
+/// The remote is newly added if it does not exist yet.
+pub fn set_remote<P: AsRef<Path>>(path: P, url: &str) -> Result<()> {
+    let repo = Repository::open(path)?;
+    let name = get_remote_name(&repo)
+        .ok()
+        .and_then(|name| name)
+        .unwrap_or_else(|| "origin".to_owned());
+    if repo.find_remote(&name).is_ok() {
+        repo.remote_set_url(&name, url)?;
+    } else {
+        repo.remote(&name, url)?;
+    }
+    Ok(())
+}
+
+pub fn status<P: AsRef<Path>>(path: P) -> Result<Status> {
+    let repo = Repository::open(path)?;
+    let mut options = StatusOptions::new();
+    options
+        .include_untracked(true)
+        .recurse_untracked_dirs(true)
+        .include_ignored(false);
+    let changed = repo.statuses(Some(&mut options))?.len();
+    Ok(Status {
+        changed,
+        ahead: ahead_of_upstream(&repo),
+    })
+}
+
+fn ahead_of_upstream(repo: &Repository) -> Option<usize> {
+    let head = repo.head().ok()?;
+    if !head.is_branch() {
+        return None;
+    }
+    let branch = Branch::wrap(head);
+    let upstream = branch.upstream().ok()?;
+    let (ahead, _behind) = repo
+        .graph_ahead_behind(branch.get().target()?, upstream.get().target()?)
+        .ok()?;
+    Some(ahead)
+}
+
+/// Returns the committer date of HEAD, or `None` if the current branch has no commits yet.
+pub fn last_commit_time<P: AsRef<Path>>(path: P) -> Result<Option<DateTime<Local>>> {
+    let repo = Repository::open(path)?;
+    let commit = match repo.head().and_then(|head| head.peel_to_commit()) {
+        Ok(commit) => commit,
+        Err(..) => return Ok(None),
+    };
+    let time = commit.committer().when();
+    Ok(Local.timestamp_opt(time.seconds(), 0).single())
+}
+
+/// Returns the default branch of the remote, which `refs/remotes/<remote>/HEAD` points to.
+pub fn default_branch<P: AsRef<Path>>(path: P) -> Result<Option<String>> {
+    let repo = Repository::open(path)?;
+    let remote = match get_remote_name(&repo)? {
+        Some(remote) => remote,
+        None => return Ok(None),
+    };
+    let head = match repo.find_reference(&format!("refs/remotes/{}/HEAD", remote)) {
+        Ok(head) => head,
+        Err(..) => return Ok(None),
+    };
+    let prefix = format!("refs/remotes/{}/", remote);
+    let branch = head
+        .symbolic_target()?
+        .and_then(|target| target.strip_prefix(&prefix))
+        .map(ToOwned::to_owned);
+    Ok(branch)
+}
+
+/// Returns the number of stashes, and local branches which have unpushed commits or no upstream.
+pub fn local_only_refs<P: AsRef<Path>>(path: P) -> Result<usize> {
+    let mut repo = Repository::open(path)?;
+    let mut count = 0;
+    for branch in repo.branches(Some(BranchType::Local))? {
+        let (branch, _) = branch?;
+        let upstream = match branch.upstream() {
+            Ok(upstream) => upstream,
+            Err(..) => {
+                count += 1;
+                continue;
+            }
+        };
+        let ahead = match (branch.get().target(), upstream.get().target()) {
+            (Some(local), Some(remote)) => repo.graph_ahead_behind(local, remote)?.0,
+            _ => 0,
+        };
+        if ahead > 0 {
+            count += 1;
+        }
+    }
+    repo.stash_foreach(|_, _, _| {
+        count += 1;
+        true
+    })?;
+    Ok(count)
+}
+
+/// Returns the number of ignored files and directories.
+pub fn ignored_files<P: AsRef<Path>>(path: P) -> Result<usize> {
+    let repo = Repository::open(path)?;
+    let mut options = StatusOptions::new();
+    options
+        .include_untracked(false)
+        .include_ignored(true)
+        .recurse_ignored_dirs(false);
+    let statuses = repo.statuses(Some(&mut options))?;
+    Ok(statuses
+        .iter()
+        .filter(|entry| entry.status().is_ignored())
+        .count())
+}
+
+/// Returns the paths of linked worktrees.
+pub fn linked_worktrees<P: AsRef<Path>>(path: P) -> Result<Vec<PathBuf>> {
+    let repo = Repository::open(path)?;
+    let names = repo.worktrees()?;
+    let mut paths = vec![];
+    for name in names.iter() {
+        if let Some(name) = name? {
+            paths.push(repo.find_worktree(name)?.path().to_owned());
+        }
+    }
+    Ok(paths)
+}
+
+/// Fetch from the remote returned by `get_remote_url`, or `origin` if there is no remote.
+pub fn fetch<P: AsRef<Path>>(path: P) -> Result<()> {
+    let repo = Repository::open(path)?;
+    let name = get_remote_name(&repo)
+        .ok()
+        .and_then(|name| name)
+        .unwrap_or_else(|| "origin".to_owned());
+    let mut options = FetchOptions::new();
+    options.remote_callbacks(callbacks());
+    repo.find_remote(&name)?
+        .fetch(&[] as &[&str], Some(&mut options), None)?;
+    Ok(())
+}
+
+/// Callbacks to acquire credentials in the same way as `git`:
+/// ssh-agent for SSH, and configured credential helpers for HTTPS.
+fn callbacks<'a>() -> RemoteCallbacks<'a> {
+    let mut tried = CredentialType::empty();
+    let mut callbacks = RemoteCallbacks::new();
+    callbacks.credentials(move |url, username, allowed| {
+        if allowed.contains(CredentialType::SSH_KEY) && !tried.contains(CredentialType::SSH_KEY) {
+            tried |= CredentialType::SSH_KEY;
+            return Cred::ssh_key_from_agent(username.unwrap_or("git"));
+        }
+        if allowed.contains(CredentialType::USER_PASS_PLAINTEXT)
+            && !tried.contains(CredentialType::USER_PASS_PLAINTEXT)
+        {
+            tried |= CredentialType::USER_PASS_PLAINTEXT;
+            let config = git2::Config::open_default()?;
+            return Cred::credential_helper(&config, url, username);
+        }
+        if allowed.contains(CredentialType::DEFAULT) && !tried.contains(CredentialType::DEFAULT) {
+            tried |= CredentialType::DEFAULT;
+            return Cred::default();
+        }
+        Err(git2::Error::from_str("no available credentials"))
+    });
+    callbacks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vcs::{git, Vcs, VcsConfig};
+    use git2::{Oid, Signature};
+    use std::fs;
+    use tempfile::TempDir;
+
+    fn commit(repo: &Repository, file: &str) -> Oid {
+        let workdir = repo.workdir().unwrap();
+        fs::write(workdir.join(file), file).unwrap();
+        let mut index = repo.index().unwrap();
+        index.add_path(Path::new(file)).unwrap();
+        index.write().unwrap();
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+        let sig = Signature::now("rhq", "rhq@example.com").unwrap();
+        let parent = repo.head().ok().and_then(|head| head.peel_to_commit().ok());
+        let parents: Vec<_> = parent.iter().collect();
+        repo.commit(Some("HEAD"), &sig, &sig, file, &tree, &parents)
+            .unwrap()
+    }
+
+    /// Create a non-bare repository `seed` with a commit, and a bare repository `up.git` cloned from it.
+    fn setup() -> (TempDir, String) {
+        let dir = tempfile::tempdir().unwrap();
+        let seed = Repository::init(dir.path().join("seed")).unwrap();
+        commit(&seed, "README");
+        let upstream = dir.path().join("up.git");
+        RepoBuilder::new()
+            .bare(true)
+            .clone(dir.path().join("seed").to_str().unwrap(), &upstream)
+            .unwrap();
+        let url = upstream.to_str().unwrap().to_owned();
+        (dir, url)
+    }
+
+    #[test]
+    fn init_repository() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("new");
+        init(&path).unwrap();
+        assert!(matches!(
+            super::super::detect_from_path(&path),
+            Some(Vcs::Git)
+        ));
+    }
+
+    #[test]
+    fn clone_and_get_remote_url() {
+        let (dir, url) = setup();
+        let dest = dir.path().join("work");
+        clone(&url, &dest).unwrap();
+
+        assert_eq!(get_remote_url(&dest).unwrap(), Some(url));
+        let config = VcsConfig::new(Vcs::Git);
+        assert_eq!(
+            get_remote_url(&dest).unwrap(),
+            git::get_remote_url(&config, &dest).unwrap()
+        );
+    }
+
+    #[test]
+    fn clone_missing_remote() {
+        let dir = tempfile::tempdir().unwrap();
+        let url = format!("file://{}", dir.path().join("missing.git").display());
+        match clone(&url, dir.path().join("work")) {
+            Err(Error::RemoteNotFound { .. }) => {}
+            r => panic!("unexpected result: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn status_of_working_directory() {
+        let (dir, url) = setup();
+        let dest = dir.path().join("work");
+        clone(&url, &dest).unwrap();
+        let config = VcsConfig::new(Vcs::Git);
+
+        let st = status(&dest).unwrap();
+        assert!(st.is_clean());
+        assert_eq!(st.ahead, Some(0));
+        assert_eq!(st, git::status(&config, &dest).unwrap());
+
+        fs::write(dest.join("untracked"), "").unwrap();
+        let st = status(&dest).unwrap();
+        assert_eq!(st.changed, 1);
+        assert_eq!(st, git::status(&config, &dest).unwrap());
+
+        commit(&Repository::open(&dest).unwrap(), "untracked");
+        let st = status(&dest).unwrap();
+        assert_eq!(st.changed, 0);
+        assert_eq!(st.ahead, Some(1));
+        assert_eq!(st, git::status(&config, &dest).unwrap());
+    }
+
+    #[test]
+    fn set_remote_and_fetch() {
+        let (dir, url) = setup();
+        let dest = dir.path().join("work");
+        clone(&url, &dest).unwrap();
+
+        // a new commit is only in `seed`.
+        let seed = Repository::open(dir.path().join("seed")).unwrap();
+        let oid = commit(&seed, "CHANGES");
+
+        let seed_url = dir.path().join("seed").to_str().unwrap().to_owned();
+        set_remote(&dest, &seed_url).unwrap();
+        assert_eq!(get_remote_url(&dest).unwrap(), Some(seed_url));
+
+        fetch(&dest).unwrap();
+        let repo = Repository::open(&dest).unwrap();
+        let branch = seed.head().unwrap().shorthand().unwrap().to_owned();
+        let fetched = repo
+            .find_reference(&format!("refs/remotes/origin/{}", branch))
+            .unwrap();
+        assert_eq!(fetched.target(), Some(oid));
+    }
+}
@@ -1,45 +1,83 @@
+use super::{CloneOptions, Vcs, VcsBackend, VcsConfig};
+use crate::error::{Error, Result};
 use crate::util::process;
-use anyhow::{anyhow, Result};
-use std::{ffi::OsStr, fs, path::Path};
+use std::{
+    ffi::{OsStr, OsString},
+    fs,
+    path::Path,
+    process::Command,
+};
 
-pub fn init<P>(path: P) -> Result<()>
+pub fn init<P>(config: &VcsConfig, path: P) -> Result<()>
 where
     P: AsRef<Path>,
 {
     fs::create_dir_all(&path)?;
-    process::inherit("pijul")
-        .arg("init")
-        .current_dir(path)
-        .status()
-        .map_err(Into::into)
-        .and_then(|st| match st.code() {
-            Some(0) => Ok(()),
-            st => Err(anyhow!(
-                "command 'pijul' is exited with return code {:?}.",
-                st
-            )),
-        })
+    process::status(config.inherit().arg("init").current_dir(path))
 }
 
-pub fn clone<P, U, I, S>(url: U, path: P, args: I) -> Result<()>
+/// Build the command to clone `url` into `path`.
+///
+/// `args` are placed between the subcommand and the positional arguments.
+pub fn clone_command<P, U, I, S>(config: &VcsConfig, url: U, path: P, args: I) -> Command
 where
     P: AsRef<Path>,
     U: AsRef<str>,
     I: IntoIterator<Item = S>,
     S: AsRef<OsStr>,
 {
-    let path = format!("{}", path.as_ref().display());
-    process::inherit("pijul")
+    let mut command = config.inherit();
+    command
         .arg("clone")
         .args(args)
-        .args(&[url.as_ref(), &path])
-        .status()
-        .map_err(Into::into)
-        .and_then(|st| match st.code() {
-            Some(0) => Ok(()),
-            st => Err(anyhow!(
-                "command 'pijul' is exited with return code {:?}.",
-                st
-            )),
-        })
+        .arg(url.as_ref())
+        .arg(path.as_ref());
+    command
+}
+
+/// The backend of Pijul.
+pub struct PijulBackend;
+
+impl VcsBackend for PijulBackend {
+    fn vcs(&self) -> Vcs {
+        Vcs::Pijul
+    }
+
+    fn detect(&self, path: &Path) -> bool {
+        path.join(".pijul").exists()
+    }
+
+    fn init(&self, config: &VcsConfig, path: &Path) -> Result<()> {
+        init(config, path)
+    }
+
+    fn clone_command(
+        &self,
+        config: &VcsConfig,
+        url: &str,
+        path: &Path,
+        args: &[OsString],
+    ) -> Command {
+        clone_command(config, url, path, args)
+    }
+
+    fn clone_args(&self, options: &CloneOptions) -> Result<Vec<String>> {
+        let unsupported = |operation| {
+            Err(Error::Unsupported {
+                vcs: Vcs::Pijul,
+                operation,
+            })
+        };
+        if options.depth.is_some() {
+            return unsupported("cloning with --depth");
+        }
+        if options.bare {
+            return unsupported("cloning with --bare");
+        }
+        let mut args = vec![];
+        if let Some(ref branch) = options.branch {
+            args.extend(["--channel".to_owned(), branch.clone()]);
+        }
+        Ok(args)
+    }
 }
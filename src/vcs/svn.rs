@@ -0,0 +1,210 @@
+use super::{Status, Vcs, VcsBackend, VcsConfig, Version};
+use crate::error::{Error, Result};
+use crate::util::process;
+use chrono::{DateTime, Local};
+use lazy_static::lazy_static;
+use regex::Regex;
+use std::{
+    ffi::{OsStr, OsString},
+    path::Path,
+    process::Command,
+};
+
+/// Build the command to check out `url` into `path`.
+///
+/// `args` are placed between the subcommand and the positional arguments.
+pub fn checkout_command<P, U, I, S>(config: &VcsConfig, url: U, path: P, args: I) -> Command
+where
+    P: AsRef<Path>,
+    U: AsRef<str>,
+    I: IntoIterator<Item = S>,
+    S: AsRef<OsStr>,
+{
+    let mut command = config.inherit();
+    command
+        .arg("checkout")
+        .args(args)
+        .arg(url.as_ref())
+        .arg(path.as_ref());
+    command
+}
+
+pub fn get_remote_url<P: AsRef<Path>>(config: &VcsConfig, path: P) -> Result<Option<String>> {
+    let output = process::output(
+        config
+            .piped()
+            .args(["info", "--show-item", "url"])
+            .current_dir(path),
+    )?;
+    if !output.status.success() {
+        return Ok(None);
+    }
+    let url = String::from_utf8_lossy(&output.stdout).trim().to_owned();
+    if url.is_empty() {
+        Ok(None)
+    } else {
+        Ok(Some(url))
+    }
+}
+
+/// Point the working copy to `url`, which must be the same repository moved to another location.
+pub fn relocate<P: AsRef<Path>>(config: &VcsConfig, path: P, url: &str) -> Result<()> {
+    process::status(config.piped().args(["relocate", url]).current_dir(path))
+}
+
+pub fn status<P: AsRef<Path>>(config: &VcsConfig, path: P) -> Result<Status> {
+    let output = process::output(config.piped().arg("status").current_dir(path))?;
+    if !output.status.success() {
+        return Err(Error::VcsFailed {
+            command: config.command.clone(),
+            code: output.status.code(),
+        });
+    }
+    Ok(Status {
+        changed: parse_status(&String::from_utf8_lossy(&output.stdout)),
+        // Commits are always made on the server.
+        ahead: Some(0),
+    })
+}
+
+/// Returns the date of the last change in the working copy.
+pub fn last_commit_time<P: AsRef<Path>>(
+    config: &VcsConfig,
+    path: P,
+) -> Result<Option<DateTime<Local>>> {
+    let output = process::output(
+        config
+            .piped()
+            .args(["info", "--show-item", "last-changed-date"])
+            .current_dir(path),
+    )?;
+    if !output.status.success() {
+        return Ok(None);
+    }
+    let date = String::from_utf8_lossy(&output.stdout);
+    Ok(DateTime::parse_from_rfc3339(date.trim())
+        .ok()
+        .map(|date| date.with_timezone(&Local)))
+}
+
+/// Count changed items in the output of `svn status`, except the definitions of externals.
+fn parse_status(output: &str) -> usize {
+    output
+        .lines()
+        .filter(|line| !line.is_empty())
+        .filter(|line| !line.starts_with("Performing status on external item"))
+        .filter(|line| !line.starts_with('X'))
+        .count()
+}
+
+/// The backend of Subversion.
+///
+/// The working copies are checked out from the server, and there is no local repository.
+pub struct SvnBackend;
+
+impl VcsBackend for SvnBackend {
+    fn vcs(&self) -> Vcs {
+        Vcs::Svn
+    }
+
+    /// Matches the messages of `svn checkout` in the C locale.
+    fn remote_not_found(&self, message: &str) -> bool {
+        lazy_static! {
+            static ref RE_NOT_FOUND: Regex = Regex::new(
+                r"(?m)^svn: E170000: (?:URL '.*' doesn't exist|Unable to open repository '.*')"
+            )
+            .unwrap();
+        }
+        RE_NOT_FOUND.is_match(message)
+    }
+
+    fn detect(&self, path: &Path) -> bool {
+        path.join(".svn").is_dir()
+    }
+
+    fn minimum_version(&self) -> Option<Version> {
+        // `svn info --show-item` was introduced in 1.9.
+        Some(Version::new(1, 9, 0))
+    }
+
+    fn init(&self, _config: &VcsConfig, _path: &Path) -> Result<()> {
+        Err(Error::Unsupported {
+            vcs: Vcs::Svn,
+            operation: "creating a repository without the server",
+        })
+    }
+
+    fn clone_command(
+        &self,
+        config: &VcsConfig,
+        url: &str,
+        path: &Path,
+        args: &[OsString],
+    ) -> Command {
+        checkout_command(config, url, path, args)
+    }
+
+    fn get_remote_url(&self, config: &VcsConfig, path: &Path) -> Result<Option<String>> {
+        get_remote_url(config, path)
+    }
+
+    fn set_remote_url(&self, config: &VcsConfig, path: &Path, url: &str) -> Result<()> {
+        relocate(config, path, url)
+    }
+
+    fn status(&self, config: &VcsConfig, path: &Path) -> Result<Status> {
+        status(config, path)
+    }
+
+    fn last_commit_time(&self, config: &VcsConfig, path: &Path) -> Result<Option<DateTime<Local>>> {
+        last_commit_time(config, path)
+    }
+
+    fn local_only_refs(&self, _config: &VcsConfig, _path: &Path) -> Result<usize> {
+        // Branches and commits are always made on the server.
+        Ok(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vcs::detect_from_path;
+    use std::fs;
+
+    #[test]
+    fn test_parse_status() {
+        let output = "?       new.txt\nM       README\nX       vendor/lib\n\nPerforming status on external item at 'vendor/lib':\n";
+        assert_eq!(parse_status(output), 2);
+        assert_eq!(parse_status(""), 0);
+    }
+
+    #[test]
+    #[ignore = "requires Subversion"]
+    fn checkout_local_repository() {
+        let dir = tempfile::tempdir().unwrap();
+        let upstream = dir.path().join("up");
+        let st = Command::new("svnadmin")
+            .arg("create")
+            .arg(&upstream)
+            .status()
+            .unwrap();
+        assert!(st.success());
+
+        let config = VcsConfig::new(Vcs::Svn);
+        let url = format!("file://{}", upstream.display());
+        let work = dir.path().join("work");
+        SvnBackend
+            .clone_repository(&config, &url, &work, &[])
+            .unwrap();
+        assert_eq!(detect_from_path(&work), Some(Vcs::Svn));
+        assert_eq!(
+            SvnBackend.get_remote_url(&config, &work).unwrap(),
+            Some(url)
+        );
+
+        assert_eq!(SvnBackend.status(&config, &work).unwrap().changed, 0);
+        fs::write(work.join("new.txt"), "").unwrap();
+        assert_eq!(SvnBackend.status(&config, &work).unwrap().changed, 1);
+    }
+}
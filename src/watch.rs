@@ -0,0 +1,127 @@
+//! Watching directories to keep the cache in sync with the file system.
+
+use crate::error::{Error, Result};
+use crate::util;
+use notify::{
+    event::{EventKind, ModifyKind},
+    Event, RecommendedWatcher, RecursiveMode, Watcher as _,
+};
+use std::{
+    collections::BTreeSet,
+    io,
+    path::{Path, PathBuf},
+    sync::mpsc::{self, Receiver, RecvTimeoutError},
+    time::Duration,
+};
+
+/// Paths changed during a burst of events.
+#[derive(Debug, Default)]
+pub struct Changes {
+    /// The paths created, removed or renamed.
+    pub paths: BTreeSet<PathBuf>,
+    /// Whether some events are lost (e.g. by overflow of the queue), so that all directories should be checked.
+    pub rescan: bool,
+}
+
+/// A recursive watcher of directories.
+pub struct Watcher {
+    // The events are delivered while the watcher is alive.
+    _watcher: RecommendedWatcher,
+    events: Receiver<notify::Result<Event>>,
+}
+
+impl Watcher {
+    /// Start watching `roots` recursively.
+    pub fn new(roots: &[PathBuf]) -> Result<Self> {
+        let (tx, events) = mpsc::channel();
+        let mut watcher = notify::recommended_watcher(tx)?;
+        for root in roots {
+            watcher.watch(root, RecursiveMode::Recursive)?;
+        }
+        Ok(Watcher {
+            _watcher: watcher,
+            events,
+        })
+    }
+
+    /// Wait for the next burst of events, which ends when no event occurs during `debounce`.
+    pub fn next(&self, debounce: Duration) -> Result<Changes> {
+        let mut changes = Changes::default();
+        let first = self.events.recv().map_err(|_| disconnected())?;
+        merge(&mut changes, first?);
+        loop {
+            match self.events.recv_timeout(debounce) {
+                Ok(event) => merge(&mut changes, event?),
+                Err(RecvTimeoutError::Timeout) => return Ok(changes),
+                Err(RecvTimeoutError::Disconnected) => return Err(disconnected()),
+            }
+        }
+    }
+}
+
+fn merge(changes: &mut Changes, event: Event) {
+    if event.need_rescan() {
+        changes.rescan = true;
+    }
+    // Reading and writing contents of files never create or remove repositories.
+    match event.kind {
+        EventKind::Access(..) | EventKind::Modify(ModifyKind::Data(..)) => {}
+        EventKind::Modify(ModifyKind::Metadata(..)) => {}
+        _ => changes.paths.extend(event.paths),
+    }
+}
+
+fn disconnected() -> Error {
+    io::Error::other("the watcher is stopped unexpectedly").into()
+}
+
+/// Returns the directories to be watched, omitting those contained in others and those missing.
+pub fn roots<'a, I>(dirs: I) -> Vec<PathBuf>
+where
+    I: IntoIterator<Item = &'a Path>,
+{
+    let dirs: BTreeSet<PathBuf> = dirs
+        .into_iter()
+        .filter_map(|dir| util::canonicalize_pretty(dir).ok())
+        .filter(|dir| dir.is_dir())
+        .collect();
+    dirs.iter()
+        .filter(|dir| !dirs.iter().any(|d| d != *dir && dir.starts_with(d)))
+        .cloned()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn roots_without_overlaps() {
+        let dir = tempfile::tempdir().unwrap();
+        let base = util::canonicalize_pretty(dir.path()).unwrap();
+        for path in &["a/b", "c"] {
+            fs::create_dir_all(base.join(path)).unwrap();
+        }
+        let dirs = [
+            base.join("a/b"),
+            base.join("a"),
+            base.join("c"),
+            base.join("d"),
+        ];
+        assert_eq!(
+            roots(dirs.iter().map(PathBuf::as_path)),
+            [base.join("a"), base.join("c")]
+        );
+    }
+
+    #[test]
+    fn watch_created_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        let base = util::canonicalize_pretty(dir.path()).unwrap();
+        let watcher = Watcher::new(std::slice::from_ref(&base)).unwrap();
+        fs::create_dir_all(base.join("a/.git")).unwrap();
+        let changes = watcher.next(Duration::from_millis(100)).unwrap();
+        assert!(changes.paths.contains(&base.join("a")));
+    }
+}
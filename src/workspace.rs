@@ -1,31 +1,44 @@
 use crate::{
-    cache::Cache,
+    cache::{Cache, CacheData},
     config::Config,
-    printer::Printer,
+    error::{Error, Result},
+    printer::{Event, Level, MessageFormat, Printer},
     query::Query,
     remote::Remote,
-    repository::Repository,
-    vcs::{self, Vcs},
+    repository::{Link, Repository},
+    scan::{self, ScanOptions},
+    util::{self, process},
+    vcs::{self, CloneOptions, Vcs},
+    watch::{self, Changes, Watcher},
 };
-use anyhow::{anyhow, Result};
-use glob::Pattern;
+use chrono::{DateTime, Local};
 use std::{
+    cmp::{Ordering, Reverse},
+    collections::{BTreeMap, BTreeSet, HashMap},
     fmt::Arguments,
-    path::{Path, PathBuf},
+    fs, iter,
+    path::{Component, Path, PathBuf},
+    time::Duration,
 };
-use walkdir::{DirEntry, WalkDir};
 
 pub struct Workspace {
     cache: Cache,
+    /// The content of cache when it was loaded, used to merge the changes into the latest one.
+    base: Option<CacheData>,
     config: Config,
     printer: Printer,
 }
 
 impl Workspace {
     pub fn new() -> Result<Self> {
-        let config = Config::new(None)?;
+        Self::open(None)
+    }
+
+    fn open(config_path: Option<&Path>) -> Result<Self> {
+        let config = Config::new(config_path)?;
         let cache = Cache::new(&config.cache_dir())?;
         Ok(Workspace {
+            base: cache.get_opt().cloned(),
             cache,
             config,
             printer: Printer::default(),
@@ -36,8 +49,25 @@ impl Workspace {
         self.config.root_dir = root.into();
     }
 
+    /// Replace the printer used for reporting progress of operations.
+    ///
+    /// With JSON output, the standard output of VCS executables is redirected into the standard error
+    /// so that it is not mixed into the stream of events.
+    pub fn with_printer(mut self, printer: Printer) -> Self {
+        self.config
+            .redirect_stdout(printer.format() == MessageFormat::Json);
+        self.printer = printer;
+        self
+    }
+
+    /// Enable or disable progress messages.
+    #[deprecated(
+        since = "0.4.0",
+        note = "use `with_printer` with a `Printer` of the desired level"
+    )]
     pub fn verbose_output(mut self, verbose: bool) -> Self {
-        self.printer.verbose = verbose;
+        self.printer
+            .set_level(if verbose { Level::Info } else { Level::Warn });
         self
     }
 
@@ -45,6 +75,10 @@ impl Workspace {
         self.printer.print(args)
     }
 
+    pub fn emit(&self, event: Event) {
+        self.printer.emit(event)
+    }
+
     /// Returns a list of managed repositories.
     /// Note that this method returns None if cache has not created yet.
     pub fn repositories(&self) -> Option<&[Repository]> {
@@ -57,46 +91,86 @@ impl Workspace {
         &self.config
     }
 
+    /// Find repositories under `root` and add them under management.
+    ///
+    /// Unless `full` is set, only the directories changed since the last import are scanned.
     pub fn import_repositories<P: AsRef<Path>>(
         &mut self,
         root: P,
-        depth: Option<usize>,
+        options: ScanOptions,
+        full: bool,
     ) -> Result<()> {
-        for path in collect_repositories(root, depth, &self.config.exclude_patterns) {
-            match self.new_repository_from_path(&path) {
-                Ok(Some(repo)) => self.add_repository(repo),
-                Ok(None) => {
-                    self.printer.print(format_args!(
-                        "Ignored: {} is not a repository\n",
-                        path.display()
-                    ));
+        let root = util::canonicalize_pretty(&root).unwrap_or_else(|_| root.as_ref().to_owned());
+        let last = match self.cache.get_opt() {
+            Some(cache) if !full => cache.scans.get(&root),
+            _ => None,
+        };
+        let (found, state) = match last {
+            Some(last) => {
+                self.printer.debug(format_args!(
+                    "Scanning directories under {} changed since {}",
+                    root.display(),
+                    last.scanned_at
+                ));
+                let (mut found, state) = scan::rescan(&root, options, &self.config, last);
+                // Repositories dropped from the cache (e.g. by `rhq refresh`) are resolved again.
+                let repos = self.repositories().unwrap_or(&[]);
+                for path in state.repositories() {
+                    if found.iter().all(|(p, _)| p != path)
+                        && repos.iter().all(|repo| repo.path() != path)
+                    {
+                        if let Some(repo) = self.new_repository_from_path(path).transpose() {
+                            found.push((path.to_owned(), repo));
+                        }
+                    }
                 }
-                Err(e) => self
-                    .printer
-                    .print(format_args!("{} {}\n", e, path.display())),
+                (found, state)
+            }
+            None => {
+                self.printer.debug(format_args!(
+                    "Scanning repositories under {}",
+                    root.display()
+                ));
+                scan::scan(&root, options, &self.config)
+            }
+        };
+        for (path, repo) in found {
+            match repo {
+                Ok(repo) => self.add_repository(repo),
+                Err(e) => self.printer.emit(Event::Warning {
+                    message: format!("{} {}", e, path.display()),
+                }),
             }
         }
+        self.cache.get_mut().scans.insert(root, state);
         Ok(())
     }
 
-    pub fn add_repository(&mut self, repo: Repository) {
+    /// Add the repository under management, or update the entry of the same location.
+    ///
+    /// The time of addition, the statistics of access and the tags are kept from the existing entry.
+    pub fn add_repository(&mut self, mut repo: Repository) {
         let repos = &mut self.cache.get_mut().repositories;
         if let Some(r) = repos.iter_mut().find(|r| r.is_same_local(&repo)) {
-            self.printer.print(format_args!(
-                "Overwrite existed entry: {}\n",
-                repo.path_string()
-            ));
+            let metadata = repo.metadata_mut();
+            metadata.added_at = metadata.added_at.or(r.metadata().added_at);
+            metadata.last_accessed = metadata.last_accessed.or(r.metadata().last_accessed);
+            metadata.access_count += r.metadata().access_count;
+            metadata.tags.extend(r.metadata().tags.iter().cloned());
+            self.printer
+                .emit(Event::RepositoryUpdated { repository: &repo });
             *r = repo;
             return;
         }
 
+        repo.metadata_mut().added_at.get_or_insert_with(Local::now);
         self.printer
-            .print(format_args!("Add new entry: {}\n", repo.path_string()));
+            .emit(Event::RepositoryAdded { repository: &repo });
         repos.push(repo);
     }
 
     pub fn add_repository_if_exists(&mut self, path: &Path) -> Result<()> {
-        let repo = match self.new_repository_from_path(path) {
+        let mut repo = match self.new_repository_from_path(path) {
             Ok(Some(repo)) => repo,
             Ok(None) => {
                 self.printer.print(format_args!(
@@ -106,24 +180,45 @@ impl Workspace {
                 return Ok(());
             }
             Err(e) => {
-                self.printer
-                    .print(format_args!("{} {}\n", e, path.display()));
+                self.printer.emit(Event::Warning {
+                    message: format!("{} {}", e, path.display()),
+                });
                 return Ok(());
             }
         };
+        repo.metadata_mut().record_access();
         self.add_repository(repo);
         Ok(())
     }
 
     pub fn drop_invalid_repositories(&mut self) {
         let mut new_repo = Vec::new();
+        let config = &self.config;
         for repo in &self.cache.get_mut().repositories {
-            let repo = match repo.clone().refresh() {
+            // The VCS may change, e.g. when a Git repository is colocated with Jujutsu.
+            let detected = vcs::detect_from_path(repo.path());
+            let vcs = detected.unwrap_or_else(|| repo.vcs());
+            let mut refreshed = repo.clone();
+            refreshed.set_vcs(vcs);
+            // A valid repository is kept even if the executable is missing, e.g. colocated with Jujutsu.
+            let refreshed = match vcs.prepare(&config.vcs(vcs)) {
+                Err(e @ Error::VcsNotFound { .. }) if detected.is_some() => {
+                    self.printer.emit(Event::Warning {
+                        message: format!("{} (kept {} as it is)", e, repo.path_string()),
+                    });
+                    Some(refreshed)
+                }
+                _ => refreshed.refresh(&config.vcs(vcs)),
+            };
+            let repo = match refreshed {
                 Some(r) => r,
-                None => continue,
+                None => {
+                    self.printer
+                        .emit(Event::RepositoryDropped { path: repo.path() });
+                    continue;
+                }
             };
-            if self
-                .config
+            if config
                 .exclude_patterns
                 .iter()
                 .all(|ex| !ex.matches(&repo.path_string()))
@@ -131,7 +226,7 @@ impl Workspace {
                 new_repo.push(repo.clone());
             } else {
                 self.printer
-                    .print(format_args!("Dropped: {}\n", repo.path_string()));
+                    .emit(Event::RepositoryDropped { path: repo.path() });
             }
         }
         self.cache.get_mut().repositories = new_repo;
@@ -144,9 +239,120 @@ impl Workspace {
             .sort_by(|a, b| a.name().cmp(b.name()));
     }
 
+    /// Watch the root directory and `includes`, and keep the cache in sync with the file system.
+    ///
+    /// Repositories created under them are added, and removed ones are dropped.
+    /// The events are handled together after no event occurs during `debounce`.
+    /// The cache is reloaded before every update, so that changes by other processes are preserved.
+    /// This method blocks until watching fails.
+    pub fn watch(&mut self, debounce: Duration) -> Result<()> {
+        let dirs = iter::once(&self.config.root_dir).chain(&self.config.include_dirs);
+        let roots = watch::roots(dirs.map(PathBuf::as_path));
+        if roots.is_empty() {
+            return Err(Error::InvalidPath {
+                path: self.config.root_dir.display().to_string(),
+                reason: "no directories to watch exist".to_owned(),
+            });
+        }
+        let watcher = Watcher::new(&roots)?;
+        for root in &roots {
+            self.printer
+                .print(format_args!("Watching {}\n", root.display()));
+        }
+        loop {
+            let changes = watcher.next(debounce)?;
+            self.apply_changes(&roots, changes)?;
+        }
+    }
+
+    fn apply_changes(&mut self, roots: &[PathBuf], changes: Changes) -> Result<()> {
+        let cache_path = self.config.cache_dir();
+        self.cache = Cache::new(&cache_path)?;
+        self.base = self.cache.get_opt().cloned();
+        let paths: Vec<&Path> = changes
+            .paths
+            .iter()
+            .map(PathBuf::as_path)
+            .filter(|path| !Cache::is_cache_file(&cache_path, path))
+            .collect();
+
+        let repos = self.repositories().unwrap_or(&[]);
+        let created: BTreeMap<PathBuf, Vcs> = paths
+            .iter()
+            .filter_map(|path| {
+                let root = roots.iter().find(|root| path.starts_with(root))?;
+                scan::enclosing_repository(root, path, &self.config)
+            })
+            .filter(|(path, _)| repos.iter().all(|repo| repo.path() != path))
+            .collect();
+        let removed: Vec<PathBuf> = repos
+            .iter()
+            .filter(|repo| {
+                changes.rescan
+                    || paths
+                        .iter()
+                        .any(|path| repo.path().starts_with(path) || path.starts_with(repo.path()))
+            })
+            .filter(|repo| vcs::detect_from_path(repo.path()).is_none())
+            .map(|repo| repo.path().to_owned())
+            .collect();
+        if created.is_empty() && removed.is_empty() && !changes.rescan {
+            return Ok(());
+        }
+
+        // Remote URLs are resolved before locking the cache, since it may take a while.
+        let created: Vec<_> = created
+            .into_iter()
+            .map(|(path, vcs)| {
+                let repo = scan::repository_from_path(&self.config, &path, vcs);
+                (path, repo)
+            })
+            .collect();
+
+        let _lock = Cache::lock(&cache_path)?;
+        self.cache = Cache::new(&cache_path)?;
+        if changes.rescan {
+            self.printer.print(format_args!(
+                "Some events are lost; scanning all directories\n"
+            ));
+            for root in roots {
+                self.import_repositories(root, ScanOptions::default(), false)?;
+            }
+        }
+        for (path, repo) in created {
+            match repo {
+                Ok(repo) => self.add_repository(repo),
+                Err(e) => self.printer.emit(Event::Warning {
+                    message: format!("{} {}", e, path.display()),
+                }),
+            }
+        }
+        let repos = &mut self.cache.get_mut().repositories;
+        for path in removed {
+            if let Some(i) = repos.iter().position(|repo| repo.path() == path) {
+                repos.remove(i);
+                self.printer.emit(Event::RepositoryDropped { path: &path });
+            }
+        }
+        self.cache.dump(&cache_path)?;
+        self.base = self.cache.get_opt().cloned();
+        Ok(())
+    }
+
     /// Save current state of workspace to cache file.
+    ///
+    /// The cache file is reloaded under the lock and the changes made since this workspace was opened
+    /// are merged into it, so that the changes by other processes in the meantime are not lost.
     pub fn save_cache(&mut self) -> Result<()> {
-        self.cache.dump(&self.config.cache_dir())?;
+        let cache_path = self.config.cache_dir();
+        let _lock = Cache::lock(&cache_path)?;
+        let mut latest = Cache::new(&cache_path)?;
+        if let Some(ours) = self.cache.get_opt() {
+            latest.merge(self.base.as_ref(), ours);
+        }
+        latest.dump(&cache_path)?;
+        self.cache = latest;
+        self.base = self.cache.get_opt().cloned();
         Ok(())
     }
 
@@ -157,115 +363,866 @@ impl Workspace {
         Ok(path)
     }
 
+    /// Determine the VCS of the remote repository, from its URL or by accessing it.
+    pub fn detect_vcs(&self, remote: &Remote) -> Result<Vcs> {
+        let vcs = vcs::detect_from_url(remote.url(), |vcs| self.config.vcs(vcs).into_owned())?;
+        self.printer.debug(format_args!(
+            "Detected VCS of {}: {}",
+            remote.url(),
+            vcs.name()
+        ));
+        Ok(vcs)
+    }
+
     pub fn default_host(&self) -> &str {
         &self.config.host
     }
 
-    pub fn for_each_repo<F: Fn(&Repository) -> Result<()>>(&self, f: F) -> Result<()> {
-        let repos = self
+    pub fn for_each_repo<F: FnMut(&Repository) -> Result<()>>(&self, mut f: F) -> Result<()> {
+        let repos = self.repositories().ok_or(Error::CacheNotInitialized)?;
+        for repo in repos {
+            f(repo)?;
+        }
+        Ok(())
+    }
+
+    /// Record an access to the managed repository which contains `path`.
+    pub fn touch_repository(&mut self, path: &Path) -> Result<()> {
+        let path = util::canonicalize_pretty(path)?;
+        let repo = self
+            .cache
+            .get_mut()
+            .repositories
+            .iter_mut()
+            .filter(|repo| path.starts_with(repo.path()))
+            .max_by_key(|repo| repo.path().components().count())
+            .ok_or(Error::NotManaged { path })?;
+        repo.metadata_mut().record_access();
+        self.printer
+            .debug(format_args!("Touched {}", repo.path_string()));
+        Ok(())
+    }
+
+    /// Returns managed repositories matching all of `keywords`, the most frecent first.
+    ///
+    /// Keywords are matched against the path case-insensitively, and the last one must also
+    /// match the name of repository. All repositories are returned if no keyword is given.
+    pub fn ranked_repositories<S: AsRef<str>>(&self, keywords: &[S]) -> Result<Vec<&Repository>> {
+        let repos = self.repositories().ok_or(Error::CacheNotInitialized)?;
+        let keywords: Vec<String> = keywords
+            .iter()
+            .map(|keyword| keyword.as_ref().to_lowercase())
+            .collect();
+
+        let now = Local::now();
+        let mut ranked: Vec<(&Repository, f64)> = repos
+            .iter()
+            .filter(|repo| {
+                let path = repo.path_string().to_lowercase();
+                let name_matches = keywords
+                    .last()
+                    .is_none_or(|last| repo.name().to_lowercase().contains(last.as_str()));
+                name_matches && keywords.iter().all(|k| path.contains(k.as_str()))
+            })
+            .map(|repo| (repo, repo.metadata().frecency(now)))
+            .collect();
+        ranked.sort_by(|(a, a_score), (b, b_score)| {
+            b_score
+                .partial_cmp(a_score)
+                .unwrap_or(Ordering::Equal)
+                .then_with(|| a.path().cmp(b.path()))
+        });
+        Ok(ranked.into_iter().map(|(repo, _)| repo).collect())
+    }
+
+    /// Returns the managed repository specified by `spec`: a location in the repository,
+    /// or trailing components of its path (e.g. the name or `<owner>/<name>`).
+    pub fn find_repository(&self, spec: &str) -> Result<&Repository> {
+        let repos = self.repositories().ok_or(Error::CacheNotInitialized)?;
+        let path = Path::new(spec);
+        if path.exists() {
+            let path = util::canonicalize_pretty(path)?;
+            return repos
+                .iter()
+                .filter(|repo| path.starts_with(repo.path()))
+                .max_by_key(|repo| repo.path().components().count())
+                .ok_or(Error::NotManaged { path });
+        }
+
+        let mut found = repos.iter().filter(|repo| repo.path().ends_with(path));
+        match (found.next(), found.next()) {
+            (Some(repo), None) => Ok(repo),
+            (None, _) => Err(Error::NoMatch {
+                keywords: spec.to_owned(),
+            }),
+            (Some(_), Some(_)) => Err(Error::invalid_query(
+                spec,
+                "multiple repositories match; specify more components of the path",
+            )),
+        }
+    }
+
+    /// Returns the tags of the repository: those attached by the user,
+    /// and the names of groups in the configuration which contain it.
+    pub fn tags<'a>(&'a self, repo: &'a Repository) -> BTreeSet<&'a str> {
+        let mut tags: BTreeSet<&str> = repo.metadata().tags.iter().map(String::as_str).collect();
+        let targets = self.group_targets(repo);
+        for (name, patterns) in &self.config.groups {
+            if patterns
+                .iter()
+                .any(|pattern| targets.iter().any(|target| pattern.matches(target)))
+            {
+                tags.insert(name);
+            }
+        }
+        tags
+    }
+
+    /// Check whether the repository has all of `tags`.
+    pub fn has_tags<S: AsRef<str>>(&self, repo: &Repository, tags: &[S]) -> bool {
+        if tags.is_empty() {
+            return true;
+        }
+        let own = self.tags(repo);
+        tags.iter().all(|tag| own.contains(tag.as_ref()))
+    }
+
+    /// Returns all tags and groups, together with the number of repositories which have them.
+    pub fn all_tags(&self) -> Result<BTreeMap<&str, usize>> {
+        let repos = self.repositories().ok_or(Error::CacheNotInitialized)?;
+        let mut tags: BTreeMap<&str, usize> = self
+            .config
+            .groups
+            .keys()
+            .map(|name| (name.as_str(), 0))
+            .collect();
+        for repo in repos {
+            for tag in self.tags(repo) {
+                *tags.entry(tag).or_default() += 1;
+            }
+        }
+        Ok(tags)
+    }
+
+    /// Strings matched against the patterns of groups: the absolute path, the path relative to
+    /// the root directory, and the normalized remote URL (`<host>/<path>`).
+    fn group_targets(&self, repo: &Repository) -> Vec<String> {
+        let mut targets = vec![repo.path_string().replace('\\', "/")];
+        let root = util::canonicalize_pretty(&self.config.root_dir)
+            .unwrap_or_else(|_| self.config.root_dir.clone());
+        if let Ok(relative) = repo.path().strip_prefix(&root) {
+            targets.push(relative.to_string_lossy().replace('\\', "/"));
+        }
+        if let Some(remote) = repo.remote() {
+            targets.push(remote.normalized());
+        }
+        targets
+    }
+
+    /// Attach `tag` to the managed repository located at `path`.
+    ///
+    /// Returns `false` if the tag has already been attached.
+    pub fn add_tag(&mut self, path: &Path, tag: &str) -> Result<bool> {
+        let repo = self.repository_mut(path)?;
+        Ok(repo.metadata_mut().tags.insert(tag.to_owned()))
+    }
+
+    /// Detach `tag` from the managed repository located at `path`.
+    ///
+    /// Returns `false` if the tag is not attached.
+    pub fn remove_tag(&mut self, path: &Path, tag: &str) -> Result<bool> {
+        let repo = self.repository_mut(path)?;
+        Ok(repo.metadata_mut().tags.remove(tag))
+    }
+
+    fn repository_mut(&mut self, path: &Path) -> Result<&mut Repository> {
+        self.cache
+            .get_mut()
+            .repositories
+            .iter_mut()
+            .find(|repo| repo.path() == path)
+            .ok_or_else(|| Error::NotManaged { path: path.into() })
+    }
+
+    /// Update the last commit and the default branch of the managed repository located at `path`.
+    pub fn collect_metadata(&mut self, path: &Path) -> Result<()> {
+        let repo = self
+            .cache
+            .get_mut()
+            .repositories
+            .iter_mut()
+            .find(|repo| repo.path() == path)
+            .ok_or_else(|| Error::NotManaged { path: path.into() })?;
+        repo.collect_metadata(&self.config.vcs(repo.vcs()));
+        Ok(())
+    }
+
+    /// Measure the size on disk of the managed repository located at `path`.
+    pub fn measure_size(&mut self, path: &Path) -> Result<u64> {
+        self.repository_mut(path)?.measure_size()
+    }
+
+    /// Change the remote URL of the managed repository located at `path`.
+    pub fn set_remote_url(&mut self, path: &Path, remote: Remote) -> Result<()> {
+        let repo = self
+            .cache
+            .get_mut()
+            .repositories
+            .iter_mut()
+            .find(|repo| repo.path() == path)
+            .ok_or_else(|| Error::NotManaged { path: path.into() })?;
+        self.printer.print(format_args!(
+            "Set remote URL of {}: {}\n",
+            repo.path_string(),
+            remote.url()
+        ));
+        repo.set_remote(&self.config.vcs(repo.vcs()), remote)
+    }
+
+    /// Returns the location where the repository should be placed according to its remote,
+    /// or `None` if it cannot be determined.
+    pub fn canonical_location(&self, repo: &Repository) -> Option<PathBuf> {
+        let query: Query = repo.remote()?.url().parse().ok()?;
+        let path = self.resolve_query(&query).ok()?;
+        let relative = path.strip_prefix(&self.config.root_dir).ok()?;
+        let root = util::canonicalize_pretty(&self.config.root_dir)
+            .unwrap_or_else(|_| self.config.root_dir.clone());
+        Some(root.join(relative))
+    }
+
+    /// Returns whether `repo` has linked worktrees, including those not under management.
+    fn has_worktrees(&self, repo: &Repository) -> Result<bool> {
+        let managed = self
             .repositories()
-            .ok_or_else(|| anyhow!("The cache has not initialized yet"))?;
+            .into_iter()
+            .flatten()
+            .any(|r| match r.link() {
+                Some(Link::Worktree { main }) => main == repo.path(),
+                _ => false,
+            });
+        if managed {
+            return Ok(true);
+        }
+        let vcs = repo.vcs();
+        let worktrees = vcs.linked_worktrees(&self.config.vcs(vcs), repo.path())?;
+        Ok(!worktrees.is_empty())
+    }
+
+    /// Returns a managed repository located inside `repo`, if any.
+    fn nested_repository(&self, repo: &Repository) -> Option<&Repository> {
+        self.repositories()
+            .into_iter()
+            .flatten()
+            .find(|r| r.path() != repo.path() && r.path().starts_with(repo.path()))
+    }
+
+    /// Returns the managed repositories which are not placed at the location determined from
+    /// their remotes, together with the destinations.
+    ///
+    /// Linked worktrees, submodules and repositories which have linked worktrees are excluded,
+    /// since moving them breaks the links between them.
+    pub fn misplaced_repositories(&self) -> Result<Vec<(&Repository, PathBuf)>> {
+        let repos = self.repositories().ok_or(Error::CacheNotInitialized)?;
+        let mut misplaced = vec![];
         for repo in repos {
-            f(&repo)?;
+            // A repository whose worktrees cannot be listed is not moved either.
+            if repo.link().is_some() || self.has_worktrees(repo).unwrap_or(true) {
+                continue;
+            }
+            if let Some(dest) = self.canonical_location(repo) {
+                if dest != repo.path() {
+                    misplaced.push((repo, dest));
+                }
+            }
+        }
+        Ok(misplaced)
+    }
+
+    /// Move the managed repository located at `path` into `dest`.
+    pub fn move_repository(&mut self, path: &Path, dest: &Path) -> Result<()> {
+        let repo = self
+            .cache
+            .get_mut()
+            .repositories
+            .iter_mut()
+            .find(|repo| repo.path() == path)
+            .ok_or_else(|| Error::NotManaged { path: path.into() })?;
+        self.printer.print(format_args!(
+            "Move {} into {}\n",
+            repo.path_string(),
+            dest.display()
+        ));
+        repo.move_to(dest)
+    }
+
+    /// Returns groups of managed repositories which share the same remote,
+    /// together with the normalized remote URL.
+    ///
+    /// Repositories without remote, linked worktrees and submodules are never regarded as duplicated.
+    pub fn duplicated_repositories(&self) -> Result<Vec<(String, Vec<&Repository>)>> {
+        let repos = self.repositories().ok_or(Error::CacheNotInitialized)?;
+
+        let mut groups: Vec<(String, Vec<&Repository>)> = Vec::new();
+        let mut indices: HashMap<String, usize> = HashMap::new();
+        for repo in repos.iter().filter(|repo| repo.link().is_none()) {
+            let key = match repo.remote() {
+                Some(remote) => remote.normalized(),
+                None => continue,
+            };
+            match indices.get(&key) {
+                Some(&i) => groups[i].1.push(repo),
+                None => {
+                    indices.insert(key.clone(), groups.len());
+                    groups.push((key, vec![repo]));
+                }
+            }
+        }
+        groups.retain(|(_, group)| group.len() > 1);
+
+        Ok(groups)
+    }
+
+    /// Returns managed repositories with neither commits nor accesses in the last `days` days,
+    /// together with their sizes in bytes and the time of last activity, the largest first.
+    ///
+    /// Repositories with local changes, linked worktrees, submodules and repositories with
+    /// linked worktrees are excluded, as well as those whose activity is unknown.
+    pub fn stale_repositories(
+        &self,
+        days: u32,
+    ) -> Result<Vec<(&Repository, u64, DateTime<Local>)>> {
+        let repos = self.repositories().ok_or(Error::CacheNotInitialized)?;
+        let threshold = Local::now() - chrono::Duration::days(days.into());
+
+        let mut stale = vec![];
+        for repo in repos {
+            if repo.link().is_some() || self.nested_repository(repo).is_some() {
+                continue;
+            }
+            let metadata = repo.metadata();
+            if metadata.last_accessed.is_some_and(|time| time >= threshold) {
+                continue;
+            }
+            // The cached time of the last commit may be outdated by commits made outside of rhq.
+            let vcs = repo.vcs();
+            let last_commit_at = match vcs.last_commit_time(&self.config.vcs(vcs), repo.path()) {
+                Ok(time) => time,
+                Err(Error::Unsupported { .. }) => metadata.last_commit_at,
+                Err(e) => {
+                    self.printer
+                        .print(format_args!("Skipped: {} ({})\n", e, repo.path_string()));
+                    continue;
+                }
+            };
+            let last_active = match last_commit_at.max(metadata.last_accessed) {
+                Some(last_active) if last_active < threshold => last_active,
+                _ => continue,
+            };
+            if let Err(e) = self.check_removable(repo) {
+                self.printer.print(format_args!("Skipped: {}\n", e));
+                continue;
+            }
+            let size = util::dir_size(repo.path()).unwrap_or_default();
+            stale.push((repo, size, last_active));
+        }
+        stale.sort_by_key(|&(_, size, _)| Reverse(size));
+        Ok(stale)
+    }
+
+    /// Check that the repository can be deleted without losing anything but its files:
+    /// it is neither a linked worktree nor has linked worktrees, contains no other managed repositories,
+    /// and has no local changes.
+    fn check_removable(&self, repo: &Repository) -> Result<()> {
+        let reason = if repo.link().is_some() {
+            "it is a linked worktree or a submodule".to_owned()
+        } else if let Some(nested) = self.nested_repository(repo) {
+            format!("another repository {} is inside", nested.path_string())
+        } else if self.has_worktrees(repo)? {
+            "linked worktrees exist".to_owned()
+        } else {
+            return self.check_local_changes(repo);
+        };
+        Err(Error::LocalChanges {
+            path: repo.path().to_owned(),
+            reason,
+        })
+    }
+
+    /// Check that the repository has neither uncommitted changes, unpushed commits, nor other
+    /// branches or stashes which exist only in the local.
+    ///
+    /// An error is returned if the VCS cannot tell them.
+    fn check_local_changes(&self, repo: &Repository) -> Result<()> {
+        let vcs = repo.vcs();
+        let config = self.config.vcs(vcs);
+        let status = vcs.status(&config, repo.path())?;
+        let reason = if status.changed > 0 {
+            format!("{} uncommitted changes", status.changed)
+        } else if status.ahead != Some(0) {
+            match status.ahead {
+                Some(ahead) => format!("{} unpushed commits", ahead),
+                None => {
+                    "unpushed commits cannot be checked, as the upstream is not set or unreachable"
+                        .to_owned()
+                }
+            }
+        } else {
+            match vcs.local_only_refs(&config, repo.path())? {
+                0 => return Ok(()),
+                n => format!("{} local-only branches or stashes", n),
+            }
+        };
+        Err(Error::LocalChanges {
+            path: repo.path().to_owned(),
+            reason,
+        })
+    }
+
+    /// Delete the managed repository located at `path`, and drop it from the cache.
+    ///
+    /// If `archive_dir` is given, the repository is archived into it as a gzipped tarball before deletion.
+    /// Repositories with local changes are never removed,
+    /// nor are those with ignored files (e.g. local configurations) unless they are archived.
+    pub fn remove_repository(&mut self, path: &Path, archive_dir: Option<&Path>) -> Result<()> {
+        let repo = self
+            .repositories()
+            .and_then(|repos| repos.iter().find(|repo| repo.path() == path))
+            .ok_or_else(|| Error::NotManaged { path: path.into() })?;
+        self.check_removable(repo)?;
+        if archive_dir.is_none() {
+            let vcs = repo.vcs();
+            let reason = match vcs.ignored_files(&self.config.vcs(vcs), path) {
+                Ok(0) => None,
+                Ok(n) => Some(format!(
+                    "{} ignored files, which are kept only by archiving",
+                    n
+                )),
+                Err(Error::Unsupported { .. }) => {
+                    Some("ignored files cannot be checked without archiving".to_owned())
+                }
+                Err(e) => return Err(e),
+            };
+            if let Some(reason) = reason {
+                return Err(Error::LocalChanges {
+                    path: path.to_owned(),
+                    reason,
+                });
+            }
+        }
+
+        if let Some(dir) = archive_dir {
+            // The layout under the root directory is mirrored, so that e.g. `alice/dotfiles` and
+            // `bob/dotfiles` are not archived into the same file.
+            let root = util::canonicalize_pretty(&self.config.root_dir)
+                .unwrap_or_else(|_| self.config.root_dir.clone());
+            let relative = match path.strip_prefix(&root) {
+                Ok(relative) if relative.file_name().is_some() => relative.to_owned(),
+                _ => path
+                    .components()
+                    .filter(|c| matches!(c, Component::Normal(_)))
+                    .collect(),
+            };
+            let archive = dir.join(relative).with_file_name(format!(
+                "{}-{}.tar.gz",
+                repo.name(),
+                Local::now().format("%Y%m%d")
+            ));
+            if archive.exists() {
+                return Err(Error::DestinationExists { path: archive });
+            }
+            if let Some(parent) = archive.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            let parent = path.parent().ok_or_else(|| Error::InvalidPath {
+                path: repo.path_string(),
+                reason: "cannot archive the root directory".to_owned(),
+            })?;
+            process::status(
+                process::piped("tar")
+                    .arg("-czf")
+                    .arg(&archive)
+                    .arg("-C")
+                    .arg(parent)
+                    .arg(repo.name()),
+            )?;
+            self.printer.print(format_args!(
+                "Archived {} into {}\n",
+                repo.path_string(),
+                archive.display()
+            ));
         }
+
+        fs::remove_dir_all(path)?;
+        self.cache
+            .get_mut()
+            .repositories
+            .retain(|repo| repo.path() != path);
+        self.printer.emit(Event::RepositoryDropped { path });
         Ok(())
     }
 
     fn new_repository_from_path(&self, path: &Path) -> Result<Option<Repository>> {
-        let vcs = match vcs::detect_from_path(&path) {
+        let vcs = match vcs::detect_from_path(path) {
             Some(vcs) => vcs,
             None => return Ok(None),
         };
-        let remote = match vcs.get_remote_url(&path)? {
-            Some(remote) => remote,
-            None => return Ok(None),
-        };
-        Repository::new(path, vcs, Remote::new(remote)).map(Some)
+        scan::repository_from_path(&self.config, path, vcs).map(Some)
     }
 
     pub fn create_repository(&mut self, query: &Query, vcs: Vcs, is_ssh: bool) -> Result<()> {
         let path = self.resolve_query(query)?;
 
-        self.printer.print(format_args!(
-            "Creating an empty repository at \"{}\" (VCS: {:?})\n",
-            path.display(),
-            vcs
-        ));
+        self.printer.emit(Event::InitStarted { path: &path, vcs });
         if vcs::detect_from_path(&path).is_some() {
             self.printer.print(format_args!(
-                "[info] The repository {} has already existed.\n",
+                "The repository {} has already existed.\n",
                 path.display()
             ));
             return Ok(());
         }
-        vcs.do_init(&path)?;
-        let remote = Remote::from_query(&query, is_ssh, self.default_host()).ok();
+        let config = self.config.vcs(vcs);
+        vcs.do_init(&config, &path)?;
+        let remote = Remote::from_query(query, is_ssh, self.default_host()).ok();
         if let Some(ref remote) = remote {
-            vcs.set_remote_url(&path, remote.url())?;
+            vcs.set_remote_url(&config, &path, remote.url())?;
         }
-        let repo = Repository::new(path, vcs, remote)?;
+        let mut repo = Repository::new(path, vcs, remote)?;
+        repo.metadata_mut().record_access();
         self.add_repository(repo);
 
         Ok(())
     }
 
-    pub fn clone_repository(&mut self, remote: Remote, dest: &Path, vcs: Vcs) -> Result<()> {
-        self.printer.print(format_args!(
-            "[info] Clone from {} into {} by using {:?}\n",
-            remote.url(),
-            dest.display(),
+    /// Clone the remote repository into `dest`, and add it under management.
+    ///
+    /// The clone command receives `clone_args` in the configuration followed by `options`.
+    pub fn clone_repository(
+        &mut self,
+        remote: Remote,
+        dest: &Path,
+        vcs: Vcs,
+        options: &CloneOptions,
+    ) -> Result<()> {
+        self.printer.emit(Event::CloneStarted {
+            url: remote.url(),
+            path: dest,
             vcs,
-        ));
-        if vcs::detect_from_path(&dest).is_some() {
+        });
+        if vcs::detect_from_path(dest).is_some() {
             self.printer.print(format_args!(
                 "The repository {} has already existed.\n",
                 dest.display()
             ));
             return Ok(());
         }
-        vcs.do_clone(&dest, &remote.url(), &[] as &[String])?;
-        let repo = Repository::new(dest, vcs, remote)?;
+        let config = self.config.vcs(vcs);
+        let args = config
+            .clone_args
+            .iter()
+            .cloned()
+            .chain(options.to_args(vcs)?);
+        vcs.do_clone(&config, dest, remote.url(), args)?;
+        let mut repo = Repository::new(dest, vcs, remote)?;
+        repo.metadata_mut().record_access();
         self.add_repository(repo);
         Ok(())
     }
 }
 
-fn collect_repositories<P>(root: P, depth: Option<usize>, excludes: &[Pattern]) -> Vec<PathBuf>
-where
-    P: AsRef<Path>,
-{
-    let filter = {
-        let root = root.as_ref();
-        move |entry: &DirEntry| {
-            if entry.path() == root {
-                return true;
-            }
-            !entry
-                .path()
-                .parent()
-                .map(|path| vcs::detect_from_path(&path).is_some())
-                .unwrap_or(false)
-                && entry
-                    .path()
-                    .canonicalize()
-                    .ok()
-                    .map(|path| {
-                        let path = path.to_str().unwrap().trim_start_matches(r"\\?\");
-                        excludes.iter().all(|ex| !ex.matches(path))
-                    })
-                    .unwrap_or(false)
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Open a workspace whose root directory is `<dir>/root`.
+    fn workspace(dir: &Path) -> Workspace {
+        let config_path = dir.join("config.toml");
+        fs::write(
+            &config_path,
+            format!("root = {:?}\n", dir.join("root").display().to_string()),
+        )
+        .unwrap();
+        Workspace::open(Some(&config_path)).unwrap()
+    }
+
+    fn hg_repository(path: &Path, remote: Option<&str>) -> Repository {
+        fs::create_dir_all(path.join(".hg")).unwrap();
+        Repository::new(path, Vcs::Hg, remote.map(Remote::new)).unwrap()
+    }
+
+    fn git(dir: &Path, args: &[&str]) {
+        let status = process::piped("git")
+            .args(args)
+            .current_dir(dir)
+            .status()
+            .expect("git is required to run this test");
+        assert!(status.success(), "git {:?}", args);
+    }
+
+    /// Commit all the changes in the working directory, without user configurations.
+    fn commit(dir: &Path) {
+        git(dir, &["add", "--all"]);
+        git(
+            dir,
+            &[
+                "-c",
+                "user.name=rhq",
+                "-c",
+                "user.email=rhq@example.com",
+                "commit",
+                "-q",
+                "--allow-empty",
+                "-m",
+                "commit",
+            ],
+        );
+    }
+
+    #[test]
+    fn clone_bare_repository() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = util::canonicalize_pretty(dir.path()).unwrap();
+        git(&dir, &["init", "--quiet", "seed"]);
+        let url = dir.join("seed").display().to_string();
+
+        let mut workspace = workspace(&dir);
+        let dest = dir.join("root/bare.git");
+        let options = CloneOptions {
+            bare: true,
+            ..CloneOptions::default()
+        };
+        workspace
+            .clone_repository(Remote::new(url.clone()), &dest, Vcs::Git, &options)
+            .unwrap();
+        assert_eq!(vcs::detect_from_path(&dest), Some(Vcs::Git));
+
+        workspace.drop_invalid_repositories();
+        let repos = workspace.repositories().unwrap();
+        assert_eq!(repos.len(), 1);
+        assert_eq!(repos[0].path(), dest);
+        assert_eq!(repos[0].vcs(), Vcs::Git);
+        assert_eq!(repos[0].remote().map(Remote::url), Some(url.as_str()));
+    }
+
+    #[test]
+    fn misplaced_repositories() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = util::canonicalize_pretty(dir.path()).unwrap();
+        let mut workspace = workspace(&dir);
+        let root = dir.join("root");
+        workspace.add_repository(hg_repository(
+            &dir.join("elsewhere/bar"),
+            Some("https://github.com/foo/bar"),
+        ));
+        workspace.add_repository(hg_repository(
+            &root.join("github.com/foo/baz"),
+            Some("https://github.com/foo/baz"),
+        ));
+        workspace.add_repository(hg_repository(&dir.join("elsewhere/local"), None));
+
+        // Moving the main working tree breaks the links from its worktrees.
+        let main = dir.join("elsewhere/main");
+        fs::create_dir_all(&main).unwrap();
+        git(&main, &["init", "-q"]);
+        commit(&main);
+        git(&main, &["worktree", "add", "-q", "../main-wt"]);
+        let remote = Remote::new("https://github.com/foo/main");
+        workspace.add_repository(Repository::new(&main, Vcs::Git, Some(remote)).unwrap());
+        workspace.add_repository(
+            Repository::new(dir.join("elsewhere/main-wt"), Vcs::Git, None).unwrap(),
+        );
+
+        let misplaced = workspace.misplaced_repositories().unwrap();
+        assert_eq!(misplaced.len(), 1);
+        assert_eq!(misplaced[0].0.path(), dir.join("elsewhere/bar"));
+        assert_eq!(misplaced[0].1, root.join("github.com/foo/bar"));
+    }
+
+    #[test]
+    fn save_cache_preserves_changes_by_others() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = util::canonicalize_pretty(dir.path()).unwrap();
+        let config_path = dir.join("config.toml");
+        let paths: Vec<_> = ["a", "b", "c", "d"]
+            .iter()
+            .map(|name| dir.join(name))
+            .collect();
+
+        let mut workspace = workspace(&dir);
+        workspace.add_repository(hg_repository(&paths[0], None));
+        workspace.add_repository(hg_repository(&paths[1], None));
+        workspace.save_cache().unwrap();
+
+        let mut first = Workspace::open(Some(&config_path)).unwrap();
+        let mut second = Workspace::open(Some(&config_path)).unwrap();
+        first.add_repository(hg_repository(&paths[2], None));
+        first.save_cache().unwrap();
+
+        second.move_repository(&paths[0], &paths[3]).unwrap();
+        assert!(second.add_tag(&paths[1], "work").unwrap());
+        second.save_cache().unwrap();
+        assert_eq!(second.repositories().unwrap().len(), 3);
+
+        let workspace = Workspace::open(Some(&config_path)).unwrap();
+        let repos = workspace.repositories().unwrap();
+        let found: Vec<_> = repos.iter().map(|repo| repo.path()).collect();
+        assert_eq!(found, [&paths[1], &paths[2], &paths[3]]);
+        assert!(workspace.has_tags(&repos[0], &["work"]));
+    }
+
+    #[test]
+    fn stale_repositories_keep_local_work() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = util::canonicalize_pretty(dir.path()).unwrap();
+        let root = dir.join("root");
+        git(&dir, &["init", "-q", "seed"]);
+        fs::write(dir.join("seed/README"), "seed").unwrap();
+        commit(&dir.join("seed"));
+        git(&dir, &["clone", "-q", "--bare", "seed", "upstream.git"]);
+
+        let mut workspace = workspace(&dir);
+        let clone = |workspace: &mut Workspace, name: &str| {
+            let path = root.join(name);
+            git(
+                &dir,
+                &["clone", "-q", "upstream.git", path.to_str().unwrap()],
+            );
+            workspace.add_repository_if_exists(&path).unwrap();
+            path
+        };
+
+        let clean = clone(&mut workspace, "clean");
+        let dirty = clone(&mut workspace, "dirty");
+        fs::write(dirty.join("README"), "modified").unwrap();
+        let untracked = clone(&mut workspace, "untracked");
+        git(&untracked, &["config", "status.showUntrackedFiles", "no"]);
+        fs::write(untracked.join("notes.txt"), "draft").unwrap();
+        let stashed = clone(&mut workspace, "stashed");
+        fs::write(stashed.join("README"), "modified").unwrap();
+        git(
+            &stashed,
+            &[
+                "-c",
+                "user.name=rhq",
+                "-c",
+                "user.email=rhq@example.com",
+                "stash",
+                "-q",
+            ],
+        );
+        let unpushed = clone(&mut workspace, "unpushed");
+        fs::write(unpushed.join("README"), "modified").unwrap();
+        commit(&unpushed);
+        let worktree = clone(&mut workspace, "worktree");
+        git(
+            &worktree,
+            &["worktree", "add", "-q", "--detach", "../../outside"],
+        );
+        let outer = clone(&mut workspace, "outer");
+        fs::write(outer.join(".git/info/exclude"), "inner/\n").unwrap();
+        let inner = clone(&mut workspace, "outer/inner");
+        let ignored = clone(&mut workspace, "alice/ignored");
+        fs::write(ignored.join(".git/info/exclude"), "*.local\n").unwrap();
+        fs::write(ignored.join("config.local"), "secret").unwrap();
+
+        let stale: Vec<PathBuf> = workspace
+            .stale_repositories(0)
+            .unwrap()
+            .into_iter()
+            .map(|(repo, ..)| repo.path().to_owned())
+            .collect();
+        assert_eq!(stale.len(), 3, "{:?}", stale);
+        for path in &[&clean, &inner, &ignored] {
+            assert!(stale.contains(path), "{}", path.display());
+        }
+
+        // The time of the last commit is queried again, instead of the cached one.
+        let metadata = workspace.repository_mut(&clean).unwrap().metadata_mut();
+        metadata.last_commit_at = Some(Local::now() - chrono::Duration::days(30));
+        metadata.last_accessed = metadata.last_commit_at;
+        assert!(workspace.stale_repositories(1).unwrap().is_empty());
+
+        for path in &[&dirty, &untracked, &stashed, &unpushed, &worktree, &outer] {
+            assert!(workspace.remove_repository(path, None).is_err());
+            assert!(workspace.remove_repository(path, Some(&dir)).is_err());
+            assert!(path.exists(), "{}", path.display());
         }
-    };
-
-    let mut walkdir = WalkDir::new(root.as_ref()).follow_links(true);
-    if let Some(depth) = depth {
-        walkdir = walkdir.max_depth(depth);
-    }
-    walkdir
-        .into_iter()
-        .filter_entry(filter)
-        .filter_map(Result::ok)
-        .filter(|entry| vcs::detect_from_path(entry.path()).is_some())
-        .map(|entry| entry.path().into())
-        .collect()
+
+        // Ignored files are kept only by archiving.
+        assert!(workspace.remove_repository(&ignored, None).is_err());
+        assert!(ignored.exists());
+        let archives = dir.join("archives");
+        workspace
+            .remove_repository(&ignored, Some(&archives))
+            .unwrap();
+        assert!(!ignored.exists());
+        // The layout under the root directory is mirrored in the archive directory.
+        let archive = archives.join(format!(
+            "alice/ignored-{}.tar.gz",
+            Local::now().format("%Y%m%d")
+        ));
+        assert!(archive.exists(), "{}", archive.display());
+        let output = process::output(process::piped("tar").arg("-tzf").arg(&archive)).unwrap();
+        assert!(String::from_utf8_lossy(&output.stdout).contains("ignored/config.local"));
+
+        workspace.remove_repository(&clean, None).unwrap();
+        assert!(!clean.exists());
+        let repos = workspace.repositories().unwrap();
+        assert!(repos
+            .iter()
+            .all(|repo| repo.path() != clean && repo.path() != ignored));
+    }
+
+    #[test]
+    fn metadata_is_collected_on_demand() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = util::canonicalize_pretty(dir.path()).unwrap();
+        let path = dir.join("root/repo");
+        git(&dir, &["init", "-q", path.to_str().unwrap()]);
+        commit(&path);
+
+        let mut workspace = workspace(&dir);
+        workspace.add_repository_if_exists(&path).unwrap();
+        let last_commit_at = |workspace: &Workspace| {
+            workspace.repositories().unwrap()[0]
+                .metadata()
+                .last_commit_at
+        };
+        assert_eq!(last_commit_at(&workspace), None);
+
+        workspace.collect_metadata(&path).unwrap();
+        assert!(last_commit_at(&workspace).is_some());
+        assert!(workspace.collect_metadata(&dir.join("root/other")).is_err());
+    }
+
+    #[test]
+    fn keep_repositories_without_executable() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = util::canonicalize_pretty(dir.path()).unwrap();
+        let config_path = dir.join("config.toml");
+        fs::write(
+            &config_path,
+            format!(
+                "root = {:?}\n[vcs.jj]\ncommand = \"rhq-missing-command\"\n",
+                dir.join("root").display().to_string()
+            ),
+        )
+        .unwrap();
+        let mut workspace = Workspace::open(Some(&config_path)).unwrap();
+
+        let colocated = dir.join("colocated");
+        fs::create_dir_all(&colocated).unwrap();
+        git(&colocated, &["init", "-q"]);
+        workspace.add_repository(Repository::new(&colocated, Vcs::Git, None).unwrap());
+        fs::create_dir(colocated.join(".jj")).unwrap();
+        let removed = dir.join("removed");
+        fs::create_dir_all(removed.join(".jj")).unwrap();
+        workspace.add_repository(Repository::new(&removed, Vcs::Jujutsu, None).unwrap());
+        fs::remove_dir_all(&removed).unwrap();
+
+        workspace.drop_invalid_repositories();
+        let repos = workspace.repositories().unwrap();
+        assert_eq!(repos.len(), 1);
+        assert_eq!(repos[0].path(), colocated);
+        assert_eq!(repos[0].vcs(), Vcs::Jujutsu);
+    }
 }
@@ -31,13 +31,21 @@ class Rhq {
         return;
       }
 
-      let args = ['clone', query];
+      let args = ['clone', query, '--message-format=json'];
       let proc = child_process.spawn('rhq', args);
       let out_ch = vscode.window.createOutputChannel('rhq');
       out_ch.show(true);
 
+      let buffer = '';
       proc.stdout.on('data', (data) => {
-        out_ch.append(strip_ansi(data.toString()));
+        buffer += data.toString();
+        let lines = buffer.split('\n');
+        buffer = lines.pop();
+        for (let line of lines) {
+          if (line.trim() !== '') {
+            this.handle_event(line, out_ch);
+          }
+        }
       });
 
       proc.stderr.on('data', (data) => {
@@ -61,7 +69,8 @@ class Rhq {
       return;
     }
 
-    child_process.exec('rhq list', (err, stdout, stderr) => {
+    // The most frecent repositories come first.
+    child_process.exec('rhq path --list', (err, stdout, stderr) => {
       if (err) {
         vscode.window.showInformationMessage(err.name + ": " + err.message);
       }
@@ -75,6 +84,7 @@ class Rhq {
         if (!fs.existsSync(selected)) {
           return;
         }
+        child_process.execFile('rhq', ['touch', selected], () => {});
         let uri = vscode.Uri.parse(selected);
         vscode.commands.executeCommand('vscode.openFolder', uri, in_newwindow);
       };
@@ -87,6 +97,44 @@ class Rhq {
     });
   }
 
+  private handle_event(line: string, out_ch: vscode.OutputChannel) {
+    let event;
+    try {
+      event = JSON.parse(line);
+    } catch (e) {
+      out_ch.appendLine(strip_ansi(line));
+      return;
+    }
+
+    switch (event.event) {
+      case 'clone-started':
+        out_ch.appendLine('Clone from ' + event.url + ' into ' + event.path);
+        break;
+      case 'repository-added':
+      case 'repository-updated':
+        let path = event.repository.path;
+        out_ch.appendLine('Added: ' + path);
+        vscode.window.showInformationMessage('Cloned into ' + path, 'Open', 'Open in New Window')
+          .then((selected) => {
+            if (selected === undefined) {
+              return;
+            }
+            let uri = vscode.Uri.file(path);
+            vscode.commands.executeCommand('vscode.openFolder', uri, selected === 'Open in New Window');
+          });
+        break;
+      case 'error':
+        out_ch.appendLine('Error (' + event.kind + '): ' + event.message);
+        vscode.window.showErrorMessage('rhq: ' + event.message);
+        break;
+      default:
+        if (event.message !== undefined) {
+          out_ch.appendLine(event.message);
+        }
+        break;
+    }
+  }
+
   private dump_error(reason) {
     vscode.window.showWarningMessage(reason.toString());
   }